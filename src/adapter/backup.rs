@@ -0,0 +1,262 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeDelta, TimeZone};
+
+use crate::adapter::encryption::SealedFiles;
+use crate::adapter::{Cipher, Library, ObjectDB};
+use crate::TermnoteError;
+
+const ID_FORMAT: &str = "%Y%m%d-%H%M%S-%3f";
+
+pub struct Backup {
+    pub id: String,
+    pub created_at: DateTime<Local>,
+    pub path: PathBuf,
+}
+
+/// Timestamped JSON snapshots of a library, named `<library key>-<id>.json`
+/// so that several databases can share one backups folder.
+pub struct BackupStore {
+    dir: PathBuf,
+    prefix: String,
+    keep: usize,
+    interval: TimeDelta,
+    files: SealedFiles,
+}
+
+impl BackupStore {
+    /// `key` names the library, see [`Config::library_key`](crate::Config::library_key).
+    pub fn new(dir: &Path, key: &str, keep: usize) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            prefix: format!("{}-", key),
+            keep,
+            interval: TimeDelta::zero(),
+            files: SealedFiles::default(),
+        }
+    }
+
+    /// Takes no new snapshot while the newest one is younger than `interval`, for
+    /// libraries which change too often to be copied as a whole on every change.
+    pub fn with_interval(mut self, interval: TimeDelta) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_cipher(mut self, cipher: Option<Cipher>) -> Self {
+        self.files = SealedFiles::new(cipher);
+        self
+    }
+
+    pub fn set_cipher(&mut self, cipher: Option<Cipher>) -> Result<(), TermnoteError> {
+        let paths: Vec<PathBuf> = self.list()?.into_iter().map(|backup| backup.path).collect();
+        self.files.set_cipher(&paths, cipher)
+    }
+
+    /// Writes a snapshot of `library` and drops the oldest ones beyond the configured count.
+    pub fn create(&self, library: &Library) -> Result<Option<Backup>, TermnoteError> {
+        if self.keep == 0 {
+            return Ok(None);
+        }
+        let created_at = Local::now();
+        if let Some(newest) = self.list()?.first() {
+            if created_at - newest.created_at < self.interval {
+                return Ok(None);
+            }
+        }
+        fs::create_dir_all(&self.dir)?;
+        let id = created_at.format(ID_FORMAT).to_string();
+        let path = self.dir.join(format!("{}{}.json", self.prefix, id));
+        self.files.write_json(&path, library)?;
+        for outdated in self.list()?.into_iter().skip(self.keep) {
+            fs::remove_file(outdated.path)?;
+        }
+        Ok(Some(Backup {
+            id,
+            created_at,
+            path,
+        }))
+    }
+
+    /// Lists the snapshots, newest first.
+    pub fn list(&self) -> Result<Vec<Backup>, TermnoteError> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut backups: Vec<Backup> = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let id = match path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(&self.prefix))
+                .and_then(|name| name.strip_suffix(".json"))
+            {
+                Some(id) => id.to_string(),
+                None => continue,
+            };
+            let created_at = match NaiveDateTime::parse_from_str(&id, ID_FORMAT)
+                .ok()
+                .and_then(|time| Local.from_local_datetime(&time).earliest())
+            {
+                Some(created_at) => created_at,
+                None => continue,
+            };
+            backups.push(Backup {
+                id,
+                created_at,
+                path,
+            });
+        }
+        backups.sort_by(|a, b| b.id.cmp(&a.id));
+        Ok(backups)
+    }
+
+    /// Deletes every snapshot, e.g. along with the library they were taken of.
+    pub fn remove_all(&self) -> Result<(), TermnoteError> {
+        for backup in self.list()? {
+            fs::remove_file(backup.path)?;
+        }
+        Ok(())
+    }
+
+    pub fn read(&self, id: &str) -> Result<Library, TermnoteError> {
+        let backup = self
+            .list()?
+            .into_iter()
+            .find(|backup| backup.id == id)
+            .ok_or_else(|| TermnoteError::NotFound(format!("Could not find backup {}", id)))?;
+        ObjectDB::decode_library(&self.files.read(&backup.path)?, &backup.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use super::*;
+
+    /// Library with a topic per title, read like an unversioned library file.
+    fn library(dir: &Path, titles: &[&str]) -> Library {
+        let topics: Vec<_> = titles
+            .iter()
+            .map(|title| serde_json::json!({ "title": title, "command": "ls" }))
+            .collect();
+        let path = dir.join("library.json");
+        fs::write(&path, serde_json::json!({ "topics": topics }).to_string()).unwrap();
+        ObjectDB::read_library(&path).unwrap()
+    }
+
+    fn titles(library: &Library) -> Vec<&str> {
+        library
+            .topics
+            .iter()
+            .map(|topic| topic.title.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn snapshots_can_be_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BackupStore::new(&dir.path().join("backups"), "db-0", 5);
+        let backup = store
+            .create(&library(dir.path(), &["list", "ping"]))
+            .unwrap()
+            .unwrap();
+        let listed = store.list().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, backup.id);
+        assert_eq!(titles(&store.read(&backup.id).unwrap()), ["list", "ping"]);
+        assert!(matches!(
+            store.read("20000101-000000-000"),
+            Err(TermnoteError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn only_the_newest_snapshots_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BackupStore::new(&dir.path().join("backups"), "db-0", 2);
+        for title in ["a", "b", "c"] {
+            store.create(&library(dir.path(), &[title])).unwrap();
+            // snapshots are named by the millisecond
+            sleep(Duration::from_millis(5));
+        }
+        let newest: Vec<Vec<String>> = store
+            .list()
+            .unwrap()
+            .iter()
+            .map(|backup| {
+                let library = store.read(&backup.id).unwrap();
+                titles(&library).iter().map(|t| t.to_string()).collect()
+            })
+            .collect();
+        assert_eq!(newest, [["c"], ["b"]]);
+    }
+
+    #[test]
+    fn snapshots_are_coalesced_within_the_interval() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = dir.path().join("backups");
+        let store = BackupStore::new(&backups, "db-0", 5).with_interval(TimeDelta::hours(1));
+        assert!(store
+            .create(&library(dir.path(), &["a"]))
+            .unwrap()
+            .is_some());
+        sleep(Duration::from_millis(5));
+        assert!(store
+            .create(&library(dir.path(), &["b"]))
+            .unwrap()
+            .is_none());
+        let store = BackupStore::new(&backups, "db-0", 5).with_interval(TimeDelta::zero());
+        assert!(store
+            .create(&library(dir.path(), &["b"]))
+            .unwrap()
+            .is_some());
+        assert_eq!(store.list().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn libraries_keep_their_own_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = dir.path().join("backups");
+        let first = BackupStore::new(&backups, "db-1", 5);
+        let second = BackupStore::new(&backups, "db-2", 5);
+        first.create(&library(dir.path(), &["a"])).unwrap();
+        assert_eq!(first.list().unwrap().len(), 1);
+        assert!(second.list().unwrap().is_empty());
+        second.create(&library(dir.path(), &["b"])).unwrap();
+        first.remove_all().unwrap();
+        assert!(first.list().unwrap().is_empty());
+        assert_eq!(second.list().unwrap().len(), 1);
+        let disabled = BackupStore::new(&backups, "db-3", 0);
+        assert!(disabled
+            .create(&library(dir.path(), &["a"]))
+            .unwrap()
+            .is_none());
+        assert!(disabled.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn encrypting_rewrites_the_snapshots() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut store = BackupStore::new(&dir.path().join("backups"), "db-0", 5);
+        let backup = store
+            .create(&library(dir.path(), &["list"]))
+            .unwrap()
+            .unwrap();
+        let secret = zeroize::Zeroizing::new(b"secret".to_vec());
+        store
+            .set_cipher(Some(Cipher::new(secret).unwrap()))
+            .unwrap();
+        assert!(crate::adapter::is_encrypted(
+            &fs::read(&backup.path).unwrap()
+        ));
+        assert_eq!(titles(&store.read(&backup.id).unwrap()), ["list"]);
+
+        store.set_cipher(None).unwrap();
+        assert!(ObjectDB::read_library(&backup.path).is_ok());
+    }
+}
@@ -0,0 +1,464 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::adapter::encryption::{KeySource, KEYFILE_ENV, KEY_ENV};
+use crate::adapter::profile::{unknown_profile, ProfileStore, DEFAULT_PROFILE};
+use crate::TermnoteError;
+
+/// Directory holding all termnote data, overrides every other home location.
+pub const HOME_ENV: &str = "TERMNOTE_HOME";
+/// Path of the library database file, overridden by the `--db` option.
+pub const DB_ENV: &str = "TERMNOTE_DB";
+/// Selects the storage backend, `json` or `sqlite`.
+pub const BACKEND_ENV: &str = "TERMNOTE_BACKEND";
+/// Profile to use, overridden by the `--profile` option.
+pub const PROFILE_ENV: &str = "TERMNOTE_PROFILE";
+
+/// Name of the project library discovered from the working directory upwards.
+pub const LOCAL_FILE: &str = ".termnote.json";
+
+const LEGACY_FOLDER: &str = ".termnote";
+const XDG_FOLDER: &str = "termnote";
+const SETTINGS_FILE: &str = "config.json";
+const JSON_FILE: &str = "db.json";
+const SQLITE_FILE: &str = "db.sqlite";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Backend {
+    Json,
+    Sqlite,
+}
+
+impl Backend {
+    fn from_name(name: &str) -> Result<Self, TermnoteError> {
+        match name.to_lowercase().as_str() {
+            "json" => Ok(Backend::Json),
+            "sqlite" => Ok(Backend::Sqlite),
+            other => Err(TermnoteError::Config(format!(
+                "Unknown storage backend '{}'",
+                other
+            ))),
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Backend::Json => "json",
+            Backend::Sqlite => "sqlite",
+        }
+    }
+
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("sqlite") | Some("sqlite3") | Some("db") => Backend::Sqlite,
+            _ => Backend::Json,
+        }
+    }
+}
+
+/// User preferences read from `config.json` in the termnote home directory.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(default)]
+pub struct Settings {
+    /// Number of library snapshots kept in the backups folder, 0 disables backups.
+    pub backup_count: usize,
+    /// Number of operations which can be undone, 0 disables the journal.
+    pub history_depth: usize,
+    /// Number of executed commands kept in the execution history, 0 disables it.
+    pub execution_history: usize,
+    /// Read-only libraries merged into the view. Relative paths are resolved
+    /// against the termnote home directory and the file name may contain `*`.
+    pub shared_libraries: Vec<String>,
+    /// File unlocking an encrypted library, relative to the termnote home directory.
+    pub keyfile: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            backup_count: 5,
+            history_depth: 50,
+            execution_history: 200,
+            shared_libraries: vec!["shared/*.json".to_string()],
+            keyfile: None,
+        }
+    }
+}
+
+impl Settings {
+    fn read(path: &Path) -> Result<Self, TermnoteError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read(path)?;
+        serde_json::from_slice(&contents).map_err(|e| {
+            TermnoteError::Config(format!("Invalid settings in {}: {}", path.display(), e))
+        })
+    }
+}
+
+/// Resolved locations of the termnote data and the user settings.
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub home: PathBuf,
+    pub db_path: PathBuf,
+    pub backend: Backend,
+    pub settings: Settings,
+    /// Project library layered over the global one, if any was found.
+    pub local_db_path: Option<PathBuf>,
+    /// Named profile whose library is used, `None` for the default library.
+    pub profile: Option<String>,
+}
+
+impl Config {
+    /// Resolves the data locations, `db_arg` and `profile_arg` being the values
+    /// of the `--db` and `--profile` options.
+    ///
+    /// The home directory is `$TERMNOTE_HOME`, an existing `~/.termnote`,
+    /// `$XDG_DATA_HOME/termnote` or `~/.termnote` in that order. The database is
+    /// taken from `--db`, `$TERMNOTE_DB`, the selected profile or placed inside
+    /// the home directory. The profile is taken from `--profile`,
+    /// `$TERMNOTE_PROFILE` or the one chosen with `tn profile use`.
+    pub fn resolve(
+        db_arg: Option<&Path>,
+        profile_arg: Option<&str>,
+    ) -> Result<Self, TermnoteError> {
+        let home = Self::resolve_home()?;
+        let profiles = ProfileStore::new(&home);
+        let explicit_db: Option<PathBuf> = db_arg
+            .map(Path::to_path_buf)
+            .or_else(|| non_empty_env(DB_ENV).map(PathBuf::from));
+        let profile = match explicit_db {
+            Some(_) => None,
+            None => Self::resolve_profile(&profiles, profile_arg)?,
+        };
+        let profile_db = profile
+            .as_deref()
+            .and_then(|name| profiles.existing_path(name));
+        let backend = match non_empty_env(BACKEND_ENV) {
+            Some(name) => Backend::from_name(&name)?,
+            None => explicit_db
+                .as_deref()
+                .or(profile_db.as_deref())
+                .map(Backend::from_path)
+                .unwrap_or(Backend::Json),
+        };
+        let db_path = explicit_db
+            .or(profile_db)
+            .unwrap_or_else(|| match &profile {
+                Some(name) => profiles.path(name, backend.extension()),
+                None => home.join(Self::default_file(backend)),
+            });
+        let settings = Settings::read(&home.join(SETTINGS_FILE))?;
+        let local_db_path = Self::find_local_db()?.filter(|path| *path != db_path);
+        Ok(Self {
+            home,
+            db_path,
+            backend,
+            settings,
+            local_db_path,
+            profile,
+        })
+    }
+
+    fn resolve_profile(
+        profiles: &ProfileStore,
+        profile_arg: Option<&str>,
+    ) -> Result<Option<String>, TermnoteError> {
+        let requested = profile_arg
+            .map(str::to_string)
+            .or_else(|| non_empty_env(PROFILE_ENV));
+        match requested {
+            Some(name) if name == DEFAULT_PROFILE => Ok(None),
+            Some(name) if !profiles.exists(&name) => Err(unknown_profile(&name)),
+            Some(name) => Ok(Some(name)),
+            None => profiles.active(),
+        }
+    }
+
+    fn default_file(backend: Backend) -> &'static str {
+        match backend {
+            Backend::Json => JSON_FILE,
+            Backend::Sqlite => SQLITE_FILE,
+        }
+    }
+
+    /// Same configuration pointed at the library of another profile, which
+    /// need not exist yet.
+    pub fn with_profile(&self, name: &str) -> Self {
+        let profiles = self.profiles();
+        let (db_path, profile) = if name == DEFAULT_PROFILE {
+            (self.home.join(Self::default_file(self.backend)), None)
+        } else {
+            let path = profiles
+                .existing_path(name)
+                .unwrap_or_else(|| profiles.path(name, self.backend.extension()));
+            (path, Some(name.to_string()))
+        };
+        Self {
+            local_db_path: self.local_db_path.clone().filter(|path| *path != db_path),
+            db_path,
+            profile,
+            ..self.clone()
+        }
+    }
+
+    pub fn profiles(&self) -> ProfileStore {
+        ProfileStore::new(&self.home)
+    }
+
+    /// Name of the profile in use, as shown to the user.
+    pub fn profile_name(&self) -> &str {
+        self.profile.as_deref().unwrap_or(DEFAULT_PROFILE)
+    }
+
+    /// Walks up from the working directory looking for a project library.
+    fn find_local_db() -> Result<Option<PathBuf>, TermnoteError> {
+        let cwd = env::current_dir()?;
+        Ok(cwd
+            .ancestors()
+            .map(|dir| dir.join(LOCAL_FILE))
+            .find(|path| path.is_file()))
+    }
+
+    /// Project library to write to, the discovered one or a new one in the
+    /// working directory.
+    pub fn local_db_target(&self) -> Result<PathBuf, TermnoteError> {
+        match &self.local_db_path {
+            Some(path) => Ok(path.clone()),
+            None => Ok(env::current_dir()?.join(LOCAL_FILE)),
+        }
+    }
+
+    fn resolve_home() -> Result<PathBuf, TermnoteError> {
+        if let Some(home) = non_empty_env(HOME_ENV) {
+            return Ok(PathBuf::from(home));
+        }
+        let home_dir = dirs::home_dir()
+            .ok_or_else(|| TermnoteError::Config("Could not find home directory".to_string()))?;
+        let legacy_home = home_dir.join(LEGACY_FOLDER);
+        if legacy_home.exists() {
+            return Ok(legacy_home);
+        }
+        match non_empty_env("XDG_DATA_HOME") {
+            Some(data_home) => Ok(PathBuf::from(data_home).join(XDG_FOLDER)),
+            None => Ok(legacy_home),
+        }
+    }
+
+    pub fn backup_dir(&self) -> PathBuf {
+        self.home.join("backups")
+    }
+
+    /// Undo/redo journal of the database, kept apart from the database itself.
+    pub fn journal_path(&self) -> PathBuf {
+        self.home
+            .join(format!("{}.journal.json", self.library_key()))
+    }
+
+    /// History of the commands executed from the database, next to its journal.
+    pub fn execution_log_path(&self) -> PathBuf {
+        self.home
+            .join(format!("{}.history.json", self.library_key()))
+    }
+
+    /// Name under which the journal, history and backups of the database are
+    /// kept: its file stem followed by a hash of its location, as databases in
+    /// different folders or with different formats may share their stem.
+    pub fn library_key(&self) -> String {
+        let dir = match self.db_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        // the file itself may not exist yet, its folder is resolved instead
+        let dir = dir
+            .canonicalize()
+            .or_else(|_| std::path::absolute(&dir))
+            .unwrap_or(dir);
+        let location = dir.join(self.db_path.file_name().unwrap_or_default());
+        format!(
+            "{}-{:08x}",
+            self.db_stem(),
+            fnv1a(location.as_os_str().as_encoded_bytes()) as u32
+        )
+    }
+
+    fn db_stem(&self) -> String {
+        self.db_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "db".to_string())
+    }
+
+    /// Existing files matching the `shared_libraries` setting, sorted per pattern.
+    pub fn shared_library_paths(&self) -> Result<Vec<PathBuf>, TermnoteError> {
+        let mut paths = Vec::new();
+        for pattern in &self.settings.shared_libraries {
+            for path in self.expand_pattern(pattern)? {
+                if path != self.db_path && !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+        Ok(paths)
+    }
+
+    fn expand_pattern(&self, pattern: &str) -> Result<Vec<PathBuf>, TermnoteError> {
+        let path = match pattern.strip_prefix("~/") {
+            Some(rest) => dirs::home_dir()
+                .ok_or_else(|| TermnoteError::Config("Could not find home directory".to_string()))?
+                .join(rest),
+            None => self.home.join(pattern),
+        };
+        let file_pattern = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        if !file_pattern.contains('*') {
+            return Ok(if path.is_file() { vec![path] } else { vec![] });
+        }
+        let dir = match path.parent() {
+            Some(dir) if dir.is_dir() => dir,
+            _ => return Ok(vec![]),
+        };
+        let mut matches = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry_path = entry?.path();
+            let name = entry_path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if entry_path.is_file() && wildcard_matches(&file_pattern, &name) {
+                matches.push(entry_path);
+            }
+        }
+        matches.sort();
+        Ok(matches)
+    }
+
+    /// Secret for an encrypted library: `$TERMNOTE_KEY`, `$TERMNOTE_KEYFILE`, the
+    /// `keyfile` setting or, failing those, a passphrase prompt.
+    pub fn key_source(&self) -> KeySource {
+        if let Some(passphrase) = non_empty_env(KEY_ENV) {
+            return KeySource::Passphrase(passphrase);
+        }
+        match non_empty_env(KEYFILE_ENV) {
+            Some(keyfile) => KeySource::Keyfile(PathBuf::from(keyfile)),
+            None => match &self.settings.keyfile {
+                Some(keyfile) => KeySource::Keyfile(self.home.join(keyfile)),
+                None => KeySource::Prompt,
+            },
+        }
+    }
+
+    /// Location of the JSON library inside the home directory.
+    pub fn json_path(&self) -> PathBuf {
+        self.home.join(JSON_FILE)
+    }
+}
+
+/// 64-bit FNV-1a, a hash which unlike the one of the standard library is
+/// stable across Rust versions, so file names derived from it stay the same.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Matches `name` against a pattern in which `*` stands for any number of characters.
+fn wildcard_matches(pattern: &str, name: &str) -> bool {
+    let mut parts: Vec<&str> = pattern.split('*').collect();
+    let last = parts.pop().unwrap_or_default();
+    if parts.is_empty() {
+        return name == last;
+    }
+    let Some(mut rest) = name.strip_prefix(parts[0]) else {
+        return false;
+    };
+    for part in &parts[1..] {
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+fn non_empty_env(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend_follows_the_database_extension() {
+        for path in ["notes.sqlite", "notes.sqlite3", "dir/notes.db"] {
+            assert_eq!(
+                Backend::from_path(Path::new(path)),
+                Backend::Sqlite,
+                "{}",
+                path
+            );
+        }
+        for path in ["notes.json", "notes", "notes.db.json"] {
+            assert_eq!(
+                Backend::from_path(Path::new(path)),
+                Backend::Json,
+                "{}",
+                path
+            );
+        }
+    }
+
+    fn config(db_path: &Path) -> Config {
+        Config {
+            home: PathBuf::from("/home/user/.termnote"),
+            db_path: db_path.to_path_buf(),
+            backend: Backend::from_path(db_path),
+            settings: Settings::default(),
+            local_db_path: None,
+            profile: None,
+        }
+    }
+
+    #[test]
+    fn library_key_depends_on_the_full_path() {
+        let key = config(Path::new("/notes/db.json")).library_key();
+        assert!(key.starts_with("db-"), "{}", key);
+        assert_eq!(key, config(Path::new("/notes/db.json")).library_key());
+        for other in ["/other/db.json", "/notes/db.yaml", "/notes/db"] {
+            assert_ne!(key, config(Path::new(other)).library_key(), "{}", other);
+        }
+    }
+
+    #[test]
+    fn backend_names_are_case_insensitive() {
+        assert_eq!(Backend::from_name("SQLite").unwrap(), Backend::Sqlite);
+        assert_eq!(Backend::from_name("json").unwrap(), Backend::Json);
+        assert!(Backend::from_name("yaml").is_err());
+    }
+
+    #[test]
+    fn wildcards_match_any_number_of_characters() {
+        assert!(wildcard_matches("*.json", "team.json"));
+        assert!(wildcard_matches("*.json", ".json"));
+        assert!(wildcard_matches("team-*.json", "team-ops.json"));
+        assert!(wildcard_matches("*ops*", "devops-notes"));
+        assert!(wildcard_matches("*", "anything"));
+        assert!(!wildcard_matches("*.json", "team.yaml"));
+        assert!(!wildcard_matches("team-*.json", "ops-team-a.json"));
+        assert!(!wildcard_matches("a*b*c", "acb"));
+    }
+
+    #[test]
+    fn patterns_without_wildcards_match_exactly() {
+        assert!(wildcard_matches("team.json", "team.json"));
+        assert!(!wildcard_matches("team.json", "team.json.bak"));
+        assert!(!wildcard_matches("", "team.json"));
+    }
+}
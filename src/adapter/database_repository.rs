@@ -1,106 +1,453 @@
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::{BufReader, BufWriter, Write};
-use std::path::Path;
-use std::{error::Error, fs::File};
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-const CONFIG_FOLDER: &str = "/.termnote";
-const FILE_PATH: &str = "/.termnote/db.json";
+use crate::adapter::encryption::mark_encrypted;
+use crate::adapter::file_utils::{sibling_path, write_atomic, FileLock};
+use crate::adapter::migration::{self, CURRENT_VERSION};
+use crate::adapter::recovery::{self, Recovery};
+use crate::adapter::{is_encrypted, Cipher, Repository};
+use crate::TermnoteError;
 
-#[derive(Deserialize, Serialize, Clone)]
+/// Library an entry was loaded from. Only known at runtime, it is not stored.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Origin {
+    /// The personal library in the termnote home directory.
+    #[default]
+    Global,
+    /// A `.termnote.json` project library found above the working directory.
+    Local,
+    /// A read-only team library included through the `shared_libraries` setting.
+    Shared,
+}
+
+impl Origin {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Origin::Global => "global",
+            Origin::Local => "local",
+            Origin::Shared => "shared",
+        }
+    }
+}
+
+/// One command of a multi-step topic.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub struct Step {
+    pub description: String,
+    pub command: String,
+}
+
+impl Step {
+    /// Single command running all steps in order as long as they succeed.
+    pub fn chain(steps: &[Step]) -> String {
+        steps
+            .iter()
+            .map(|step| step.command.as_str())
+            .collect::<Vec<&str>>()
+            .join(" && ")
+    }
+}
+
+/// Shell running the commands of a topic instead of the one in `$SHELL`.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Sh,
+}
+
+impl Shell {
+    pub const NAMES: [&'static str; 4] = ["bash", "zsh", "fish", "sh"];
+
+    pub fn from_name(name: &str) -> Result<Self, TermnoteError> {
+        match name {
+            "bash" => Ok(Shell::Bash),
+            "zsh" => Ok(Shell::Zsh),
+            "fish" => Ok(Shell::Fish),
+            "sh" => Ok(Shell::Sh),
+            other => Err(TermnoteError::Validation(format!(
+                "Unknown shell '{}', choose one of {}",
+                other,
+                Shell::NAMES.join(", ")
+            ))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Sh => "sh",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
 pub struct Topic {
+    pub id: String,
     pub title: String,
     pub description: String,
     pub command: String,
     pub category: String,
+    pub tags: Vec<String>,
+    /// Commands run one after the other instead of `command`, which then
+    /// only summarizes them.
+    pub steps: Vec<Step>,
+    /// Directory the commands run in, the current one if not set.
+    pub cwd: Option<String>,
+    /// Environment variables set for the commands.
+    pub env: BTreeMap<String, String>,
+    pub shell: Option<Shell>,
+    /// Asks for a typed confirmation before the commands run.
+    pub dangerous: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub use_count: u64,
+    #[serde(skip)]
+    pub origin: Origin,
 }
 
-#[derive(Deserialize, Serialize, Clone)]
+#[derive(Deserialize, Serialize, Clone, PartialEq)]
 pub struct Link {
+    pub id: String,
     pub title: String,
     pub url: String,
+    pub tags: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub use_count: u64,
+    #[serde(skip)]
+    pub origin: Origin,
+}
+
+impl Topic {
+    /// Whether the topic carries every one of the tags.
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+    }
 }
-#[derive(Deserialize, Serialize)]
+
+impl Link {
+    /// Whether the link carries every one of the tags.
+    pub fn has_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+    }
+}
+
+/// Trims and lowercases tags, splitting comma separated ones and dropping
+/// empty ones and duplicates.
+pub fn normalize_tags<S: AsRef<str>>(tags: &[S]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags.iter().flat_map(|tags| tags.as_ref().split(',')) {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Library {
+    pub version: u32,
     pub topics: Vec<Topic>,
     pub links: Vec<Link>,
 }
 
+impl Default for Library {
+    fn default() -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            topics: Vec::new(),
+            links: Vec::new(),
+        }
+    }
+}
+
+/// Generates a short random id (8 hex digits) which `is_taken` does not reject.
+pub fn generate_id(is_taken: impl Fn(&str) -> bool) -> String {
+    loop {
+        let mut hasher = RandomState::new().build_hasher();
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos())
+            .unwrap_or_default();
+        hasher.write_u128(nanos);
+        let id = format!("{:08x}", hasher.finish() as u32);
+        if !is_taken(&id) {
+            return id;
+        }
+    }
+}
+
+/// Serialization of a library file, chosen by its extension.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum FileFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl FileFormat {
+    fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => FileFormat::Toml,
+            Some("yaml") | Some("yml") => FileFormat::Yaml,
+            _ => FileFormat::Json,
+        }
+    }
+
+    /// Parses the contents into a JSON document, which the migrations work on.
+    fn parse(&self, contents: &[u8], path: &Path) -> Result<Value, TermnoteError> {
+        let parse_error = |e: &dyn std::fmt::Display| {
+            TermnoteError::Parse(format!("{} in {}", e, path.display()))
+        };
+        match self {
+            FileFormat::Json => serde_json::from_slice(contents).map_err(|e| parse_error(&e)),
+            FileFormat::Toml => {
+                let text = std::str::from_utf8(contents).map_err(|e| parse_error(&e))?;
+                toml::from_str(text).map_err(|e| parse_error(&e))
+            }
+            FileFormat::Yaml => serde_yaml::from_slice(contents).map_err(|e| parse_error(&e)),
+        }
+    }
+
+    /// Serializes the library for hand editing: pretty printed, with the keys
+    /// of every entry in declaration order.
+    fn serialize(&self, library: &Library) -> Result<Vec<u8>, TermnoteError> {
+        let contents =
+            match self {
+                FileFormat::Json => serde_json::to_string_pretty(library)? + "\n",
+                FileFormat::Toml => toml::to_string_pretty(library)
+                    .map_err(|e| TermnoteError::Parse(e.to_string()))?,
+                FileFormat::Yaml => serde_yaml::to_string(library)
+                    .map_err(|e| TermnoteError::Parse(e.to_string()))?,
+            };
+        Ok(contents.into_bytes())
+    }
+}
+
 pub struct ObjectDB {
     pub library: Library,
+    path: PathBuf,
+    cipher: Option<Cipher>,
 }
 
 impl ObjectDB {
-    pub fn init() -> Self {
-        let home_dir_rs = dirs::home_dir();
+    /// Opens the library at `path`, creating an empty one if it does not exist yet.
+    pub fn init(path: &Path) -> Result<Self, TermnoteError> {
+        Self::init_with_cipher(path, None)
+    }
 
-        let path_buf = match home_dir_rs {
-            Some(path) => path,
-            None => panic!("Could not find home directory"),
+    /// Opens the library at `path` like [`ObjectDB::init`], encrypting it with
+    /// `cipher` when given.
+    pub fn init_with_cipher(path: &Path, cipher: Option<Cipher>) -> Result<Self, TermnoteError> {
+        let mut db = Self {
+            library: Library::default(),
+            path: path.to_path_buf(),
+            cipher,
         };
-
-        let home_dir = path_buf.display().to_string();
-        let result: Result<Library, Box<dyn Error>> = {
-            let file_rs = File::open(format!("{}/{}", home_dir, FILE_PATH));
-
-            match file_rs {
-                Ok(file) => {
-                    let reader = BufReader::new(file);
-                    let library = serde_json::from_reader(reader);
-                    if library.is_err() {
-                        panic!("Could not parse file cause: {}", library.err().unwrap());
-                    }
-                    Ok(library.unwrap())
-                }
-                Err(e) => Err(format!("Could not open file cause: {}", e).into()),
+        if path.exists() {
+            db.load()?;
+            return Ok(db);
+        }
+        if let Some(config_path) = path.parent() {
+            if !config_path.as_os_str().is_empty() && !config_path.exists() {
+                fs::create_dir_all(config_path)?;
             }
+        }
+        let _lock = FileLock::acquire(&sibling_path(path, ".lock"))?;
+        // another invocation may have created it while we waited for the lock
+        if path.exists() {
+            db.load()?;
+        } else {
+            db.save()?;
+        }
+        Ok(db)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads and migrates the library at `path` without keeping it open.
+    pub fn read_library(path: &Path) -> Result<Library, TermnoteError> {
+        let contents = fs::read(path)?;
+        Self::decode_library(&contents, path)
+    }
+
+    /// Parses and migrates library contents which were read from `path`.
+    pub fn decode_library(contents: &[u8], path: &Path) -> Result<Library, TermnoteError> {
+        let (library, _) = Self::parse_library(contents, path)?;
+        Ok(library)
+    }
+
+    /// Recovers the library at `path` if it cannot be parsed: the damaged file
+    /// is moved aside and replaced by the entries which could be salvaged.
+    /// Returns `None` if the library is fine.
+    pub fn recover(path: &Path, cipher: Option<Cipher>) -> Result<Option<Recovery>, TermnoteError> {
+        let _lock = FileLock::acquire(&sibling_path(path, ".lock"))?;
+        let contents = match &cipher {
+            Some(cipher) => cipher.open(&fs::read(path)?)?,
+            None => fs::read(path)?,
+        };
+        match Self::parse_library(&contents, path) {
+            Err(TermnoteError::Parse(_)) => {}
+            Err(e) => return Err(e),
+            Ok(_) => return Ok(None),
+        }
+        let document = FileFormat::from_path(path).parse(&contents, path).ok();
+        let salvage = recovery::salvage(document, &contents);
+        let damaged_path = sibling_path(
+            path,
+            &format!(".damaged-{}", Utc::now().format("%Y%m%d-%H%M%S")),
+        );
+        fs::rename(path, &damaged_path)?;
+        let db = Self {
+            library: salvage.library,
+            path: path.to_path_buf(),
+            cipher,
         };
+        db.save()?;
+        Ok(Some(Recovery {
+            damaged_path,
+            topics: db.library.topics.len(),
+            links: db.library.links.len(),
+            lost_topics: salvage.lost_topics,
+            lost_links: salvage.lost_links,
+        }))
+    }
 
-        if let Ok(rs) = result {
-            return Self { library: rs };
-        }
-        let config_path: String = format!("{}/{}", home_dir, CONFIG_FOLDER);
-        if !Path::new(&config_path).exists() {
-            let rs = fs::create_dir(config_path);
-            if let Err(error_msg) = rs {
-                panic!(
-                    "Could not create configuration directory in home folder cause: {}",
-                    error_msg
-                )
-            }
+    /// Parses and migrates the library, returning the version it was migrated from.
+    fn parse_library(
+        contents: &[u8],
+        path: &Path,
+    ) -> Result<(Library, Option<u32>), TermnoteError> {
+        if is_encrypted(contents) {
+            return Err(TermnoteError::Encryption(format!(
+                "{} is encrypted",
+                path.display()
+            )));
         }
+        let mut document = FileFormat::from_path(path).parse(contents, path)?;
+        let migrated_from = migration::migrate(&mut document)?;
+        let library = serde_json::from_value(document)
+            .map_err(|e| TermnoteError::Parse(format!("{} in {}", e, path.display())))?;
+        Ok((library, migrated_from))
+    }
+}
 
-        let file_path: String = format!("{}/{}", home_dir, FILE_PATH);
-        let lib = Library {
-            topics: Vec::new(),
-            links: Vec::new(),
+impl Repository for ObjectDB {
+    fn load(&mut self) -> Result<(), TermnoteError> {
+        let contents = fs::read(&self.path)?;
+        let (library, migrated_from) = match &self.cipher {
+            Some(cipher) => Self::parse_library(&cipher.open(&contents)?, &self.path)?,
+            None => Self::parse_library(&contents, &self.path)?,
         };
-        if !Path::new(&file_path).exists() {
-            let file_rs = File::create(format!("{}/{}", home_dir, FILE_PATH));
-            if let Err(e) = file_rs {
-                panic!("Could not create file cause: {}", e);
-            }
-            let file: File = file_rs.unwrap();
-            let mut writer = BufWriter::new(file);
-            serde_json::to_writer(&mut writer, &lib).expect("Could not create empty database");
-            writer.flush().expect("could not write file");
+        self.library = library;
+        if let Some(version) = migrated_from {
+            // keep the original around in case the migration lost anything
+            let backup_path = sibling_path(&self.path, &format!(".v{}.bak", version));
+            write_atomic(&backup_path, &contents)?;
+            self.save()?;
         }
-        Self { library: lib }
+        Ok(())
     }
 
-    pub fn save(&self) -> Result<(), Box<dyn Error>> {
-        let home_dir_rs = dirs::home_dir();
-        if home_dir_rs.is_none() {
-            panic!("Could not find home directory");
+    fn save(&self) -> Result<(), TermnoteError> {
+        let mut contents = FileFormat::from_path(&self.path).serialize(&self.library)?;
+        if let Some(cipher) = &self.cipher {
+            contents = cipher.seal(&contents)?;
         }
-        let path_buf = home_dir_rs.unwrap();
-        let home_dir = path_buf.display().to_string();
-
-        let file = File::create(format!("{}/{}", home_dir, FILE_PATH))?;
-        let mut writer = BufWriter::new(file);
-        serde_json::to_writer(&mut writer, &self.library)?;
-        writer.flush()?;
+        write_atomic(&self.path, &contents)?;
         Ok(())
     }
+
+    fn library(&self) -> &Library {
+        &self.library
+    }
+
+    fn library_mut(&mut self) -> &mut Library {
+        &mut self.library
+    }
+
+    fn lock_path(&self) -> Option<PathBuf> {
+        Some(sibling_path(&self.path, ".lock"))
+    }
+
+    fn set_cipher(&mut self, cipher: Option<Cipher>) -> Result<(), TermnoteError> {
+        self.cipher = cipher;
+        self.save()?;
+        mark_encrypted(&self.path, self.cipher.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic(title: &str, last_used_at: Option<DateTime<Utc>>) -> Topic {
+        Topic {
+            id: generate_id(|_| false),
+            title: title.to_string(),
+            description: format!("{} description", title),
+            command: "ls -la".to_string(),
+            category: "files".to_string(),
+            tags: Vec::new(),
+            steps: Vec::new(),
+            cwd: None,
+            env: Default::default(),
+            shell: None,
+            dangerous: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            last_used_at,
+            use_count: 2,
+            origin: Origin::Global,
+        }
+    }
+
+    #[test]
+    fn every_format_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["db.json", "db.toml", "db.yaml", "db.yml"] {
+            let path = dir.path().join(name);
+            let mut db = ObjectDB::init(&path).unwrap();
+            db.library.topics = vec![topic("List", Some(Utc::now())), topic("Ping", None)];
+            db.save().unwrap();
+            let reopened = ObjectDB::init(&path).unwrap();
+            let stored = serde_json::to_value(&reopened.library).unwrap();
+            assert_eq!(
+                stored,
+                serde_json::to_value(&db.library).unwrap(),
+                "{}",
+                name
+            );
+        }
+        let json = fs::read_to_string(dir.path().join("db.json")).unwrap();
+        assert!(json.contains("\n  \"topics\""), "{}", json);
+    }
+
+    #[test]
+    fn invalid_contents_name_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.toml");
+        fs::write(&path, "topics = [").unwrap();
+        match ObjectDB::init(&path) {
+            Err(TermnoteError::Parse(message)) => assert!(message.contains("db.toml")),
+            _ => panic!("expected a parse error"),
+        }
+    }
 }
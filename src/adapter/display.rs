@@ -1,29 +1,42 @@
-use crate::adapter::{Link, Topic};
+use crate::adapter::{danger_reasons, Link, Origin, Step, Topic};
+use crate::TermnoteError;
 use colored::Colorize;
 use crossterm::event::{self, Event, KeyCode, KeyEventKind};
-use ratatui::layout::{Constraint, Layout, Margin};
+use ratatui::layout::{Constraint, Flex, Layout, Margin, Rect};
 use ratatui::prelude::CrosstermBackend;
 use ratatui::style::{Color, Modifier, Style, Stylize};
 use ratatui::text::Text;
 use ratatui::widgets::{
-    Block, Borders, Cell, HighlightSpacing, Padding, Paragraph, Row, Scrollbar,
-    ScrollbarOrientation, ScrollbarState, Table, TableState,
+    Block, Borders, Cell, Clear, HighlightSpacing, Padding, Paragraph, Row, Scrollbar,
+    ScrollbarOrientation, ScrollbarState, Table, TableState, Wrap,
 };
 use ratatui::Frame;
 use ratatui::Terminal;
-use std::error::Error;
-use std::io::Stdout;
+use std::collections::HashSet;
+use std::io::{self, Stdout, Write};
 use unicode_width::UnicodeWidthStr;
 
-fn calc_topic_len_constraint(items: &[Topic]) -> (u16, u16, u16) {
-    let title_len = items
-        .iter()
-        .map(|topic| topic.title.width())
-        .max()
-        .unwrap_or(0);
+const ID_COLUMN_WIDTH: u16 = 8;
+const ORIGIN_COLUMN_WIDTH: u16 = 6;
+
+fn calc_topic_len_constraint(items: &[Topic]) -> (u16, u16, u16, u16) {
+    let title_len =
+        items
+            .iter()
+            .map(|topic| topic_title(topic, true).width())
+            .chain(items.iter().flat_map(|topic| {
+                (0..topic.steps.len()).map(|index| step_title(topic, index).width())
+            }))
+            .max()
+            .unwrap_or(0);
     let command_len = items
         .iter()
         .map(|topic| topic.command.width())
+        .chain(
+            items
+                .iter()
+                .flat_map(|topic| topic.steps.iter().map(|step| step.command.width())),
+        )
         .max()
         .unwrap_or(0);
 
@@ -32,32 +45,140 @@ fn calc_topic_len_constraint(items: &[Topic]) -> (u16, u16, u16) {
         .map(|topic| topic.category.width())
         .max()
         .unwrap_or(0);
+    let tags_len = items
+        .iter()
+        .map(|topic| tags_text(&topic.tags).width())
+        .max()
+        .unwrap_or(0);
 
     #[allow(clippy::cast_possible_truncation)]
-    (category_len as u16, title_len as u16, command_len as u16)
+    (
+        category_len as u16,
+        title_len as u16,
+        tags_len as u16,
+        command_len as u16,
+    )
 }
 
-fn calc_link_len_constraint(items: &[Link]) -> (u16, u16) {
+fn calc_link_len_constraint(items: &[Link]) -> (u16, u16, u16) {
     let title_len = items
         .iter()
         .map(|link| link.title.width())
         .max()
         .unwrap_or(0);
     let url_len = items.iter().map(|link| link.url.width()).max().unwrap_or(0);
+    let tags_len = items
+        .iter()
+        .map(|link| tags_text(&link.tags).width())
+        .max()
+        .unwrap_or(0);
 
     #[allow(clippy::cast_possible_truncation)]
-    (title_len as u16, url_len as u16)
+    (title_len as u16, tags_len as u16, url_len as u16)
 }
 
-fn create_title() -> Paragraph<'static> {
+/// Title of the topic, marking topics with steps as expandable and
+/// dangerous topics with a warning sign.
+fn topic_title(topic: &Topic, expanded: bool) -> String {
+    let title = if topic.dangerous {
+        format!("⚠ {}", topic.title)
+    } else {
+        topic.title.clone()
+    };
+    match (topic.steps.len(), expanded) {
+        (0, _) => title,
+        (steps, true) => format!("▾ {} ({} steps)", title, steps),
+        (steps, false) => format!("▸ {} ({} steps)", title, steps),
+    }
+}
+
+fn step_title(topic: &Topic, index: usize) -> String {
+    let step = &topic.steps[index];
+    if step.description.is_empty() {
+        format!("  {}.", index + 1)
+    } else {
+        format!("  {}. {}", index + 1, step.description)
+    }
+}
+
+/// Working directory, environment and shell of the topic, if any is set.
+fn context_text(topic: &Topic) -> String {
+    let mut parts = Vec::new();
+    if let Some(cwd) = &topic.cwd {
+        parts.push(format!("in {}", cwd));
+    }
+    if !topic.env.is_empty() {
+        let env: Vec<String> = topic
+            .env
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        parts.push(format!("env {}", env.join(" ")));
+    }
+    if let Some(shell) = topic.shell {
+        parts.push(format!("shell {}", shell.name()));
+    }
+    if parts.is_empty() {
+        String::new()
+    } else {
+        format!("   ({})", parts.join(", "))
+    }
+}
+
+fn tags_text(tags: &[String]) -> String {
+    tags.join(", ")
+}
+
+/// Distinct tags of the entries in alphabetical order.
+fn collect_tags<'a>(tags: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let mut collected: Vec<String> = tags.cloned().collect();
+    collected.sort();
+    collected.dedup();
+    collected
+}
+
+/// Tag filter following `current`, cycling back to no filter after the last tag.
+fn next_tag_filter(tags: &[String], current: Option<&String>) -> Option<String> {
+    match current {
+        None => tags.first().cloned(),
+        Some(current) => tags
+            .iter()
+            .position(|tag| tag == current)
+            .and_then(|index| tags.get(index + 1))
+            .cloned(),
+    }
+}
+
+fn create_scroll_state(items: usize, item_height: usize) -> ScrollbarState {
+    if items > 1 {
+        ScrollbarState::new((items - 1) * item_height)
+    } else {
+        ScrollbarState::default()
+    }
+}
+
+/// Entries of read-only shared libraries are set apart from the personal ones.
+fn origin_style(origin: Origin) -> Style {
+    match origin {
+        Origin::Shared => Style::default().fg(Color::LightBlue),
+        _ => Style::default().fg(Color::White),
+    }
+}
+
+/// Header naming the profile in use, if it is not the default one, and the
+/// tag the entries are filtered by.
+fn create_title(profile: Option<&str>, tag_filter: Option<&str>) -> Paragraph<'static> {
     let title_block = Block::default()
         .borders(Borders::ALL)
         .style(Style::default());
-    Paragraph::new(Text::styled(
-        "TERMNOTE 📝",
-        Style::default().fg(Color::Cyan),
-    ))
-    .block(title_block)
+    let mut title = "TERMNOTE 📝".to_string();
+    if let Some(name) = profile {
+        title.push_str(&format!(" [{}]", name));
+    }
+    if let Some(tag) = tag_filter {
+        title.push_str(&format!(" #{}", tag));
+    }
+    Paragraph::new(Text::styled(title, Style::default().fg(Color::Cyan))).block(title_block)
 }
 
 fn create_url_table(library_list: &mut LibraryUrlList) -> (Table<'_>, &mut LibraryUrlList) {
@@ -67,29 +188,33 @@ fn create_url_table(library_list: &mut LibraryUrlList) -> (Table<'_>, &mut Libra
         .fg(Color::LightCyan);
 
     let selected_col_style = Style::default().fg(Color::Green);
-    let header = ["Index", "Title", "URL"]
+    let header = ["ID", "Origin", "Title", "Tags", "URL"]
         .into_iter()
         .map(Cell::from)
         .collect::<Row>()
         .style(header_style)
         .height(1);
-    let rows = library_list.links.iter().enumerate().map(|(index, link)| {
+    let rows = library_list.links.iter().map(|link| {
         [
-            Cell::from(Text::from(index.to_string())),
+            Cell::from(Text::from(link.id.clone())),
+            Cell::from(Text::from(link.origin.name())),
             Cell::from(Text::from(link.title.clone())),
+            Cell::from(Text::from(tags_text(&link.tags))),
             Cell::from(Text::from(link.url.clone())),
         ]
         .into_iter()
-        .map(|cell| cell.style(Style::default().fg(Color::White)))
+        .map(|cell| cell.style(origin_style(link.origin)))
         .collect::<Row>()
     });
     (
         Table::new(
             rows,
             [
-                Constraint::Length(5),
+                Constraint::Length(ID_COLUMN_WIDTH),
+                Constraint::Length(ORIGIN_COLUMN_WIDTH),
                 Constraint::Length(library_list.longest_item_lens.0),
-                Constraint::Min(library_list.longest_item_lens.1),
+                Constraint::Length(library_list.longest_item_lens.1),
+                Constraint::Min(library_list.longest_item_lens.2),
             ],
         )
         .header(header)
@@ -109,35 +234,53 @@ fn create_topic_table(library_list: &mut LibraryList) -> (Table<'_>, &mut Librar
         .fg(Color::LightCyan);
 
     let selected_col_style = Style::default().fg(Color::Green);
-    let header = ["Index", "Category", "Title", "Command"]
+    let header = ["ID", "Origin", "Category", "Title", "Tags", "Command"]
         .into_iter()
         .map(Cell::from)
         .collect::<Row>()
         .style(header_style)
         .height(1);
-    let rows = library_list
-        .topics
-        .iter()
-        .enumerate()
-        .map(|(index, topic)| {
+    let rows = library_list.rows.iter().map(|row| match *row {
+        TopicRow::Topic(index) => {
+            let topic = &library_list.topics[index];
+            let expanded = library_list.expanded.contains(&topic.id);
             [
-                Cell::from(Text::from(index.to_string())),
+                Cell::from(Text::from(topic.id.clone())),
+                Cell::from(Text::from(topic.origin.name())),
                 Cell::from(Text::from(topic.category.clone())),
-                Cell::from(Text::from(topic.title.clone())),
+                Cell::from(Text::from(topic_title(topic, expanded))),
+                Cell::from(Text::from(tags_text(&topic.tags))),
                 Cell::from(Text::from(topic.command.clone())),
             ]
             .into_iter()
-            .map(|cell| cell.style(Style::default().fg(Color::White)))
+            .map(|cell| cell.style(origin_style(topic.origin)))
             .collect::<Row>()
-        });
+        }
+        TopicRow::Step(index, step) => {
+            let topic = &library_list.topics[index];
+            [
+                Cell::from(""),
+                Cell::from(""),
+                Cell::from(""),
+                Cell::from(Text::from(step_title(topic, step))),
+                Cell::from(""),
+                Cell::from(Text::from(topic.steps[step].command.clone())),
+            ]
+            .into_iter()
+            .map(|cell| cell.style(Style::default().fg(Color::Gray)))
+            .collect::<Row>()
+        }
+    });
     (
         Table::new(
             rows,
             [
-                Constraint::Length(5),
+                Constraint::Length(ID_COLUMN_WIDTH),
+                Constraint::Length(ORIGIN_COLUMN_WIDTH),
                 Constraint::Length(library_list.longest_item_lens.0),
                 Constraint::Length(library_list.longest_item_lens.1),
-                Constraint::Min(library_list.longest_item_lens.2),
+                Constraint::Length(library_list.longest_item_lens.2),
+                Constraint::Min(library_list.longest_item_lens.3),
             ],
         )
         .header(header)
@@ -152,7 +295,7 @@ fn create_topic_table(library_list: &mut LibraryList) -> (Table<'_>, &mut Librar
 
 fn create_topic_footer_info() -> Paragraph<'static> {
     const INFO_TEXT: [&str; 1] =
-        ["(q) quit | (↑) move up | (↓) move down | (e) execute command | (ENTER) return command"];
+        ["(q) quit | (↑) move up | (↓) move down | (→/←) expand/collapse steps | (t) filter by tag | (e) execute command | (ENTER) return command"];
     Paragraph::new(Text::from_iter(INFO_TEXT))
         .style(Style::new().fg(Color::Blue).bg(Color::Black))
         .centered()
@@ -164,7 +307,8 @@ fn create_topic_footer_info() -> Paragraph<'static> {
 }
 
 fn create_url_footer_info() -> Paragraph<'static> {
-    const INFO_TEXT: [&str; 1] = ["(q) quit | (↑) move up | (↓) move down | (ENTER) return URL"];
+    const INFO_TEXT: [&str; 1] =
+        ["(q) quit | (↑) move up | (↓) move down | (t) filter by tag | (ENTER) return URL"];
     Paragraph::new(Text::from_iter(INFO_TEXT))
         .style(Style::new().fg(Color::Blue).bg(Color::Black))
         .centered()
@@ -194,7 +338,10 @@ fn render_all_topic_ui_blocks(library_list: &mut LibraryList, frame: &mut Frame)
     .areas(main_area);
 
     let (table, lib_list) = create_topic_table(library_list);
-    frame.render_widget(create_title(), header_area);
+    frame.render_widget(
+        create_title(lib_list.profile.as_deref(), lib_list.tag_filter.as_deref()),
+        header_area,
+    );
     frame.render_stateful_widget(table, list_area, &mut lib_list.state);
     let scroll_area = list_area.inner(Margin {
         vertical: 1,
@@ -203,6 +350,41 @@ fn render_all_topic_ui_blocks(library_list: &mut LibraryList, frame: &mut Frame)
     frame.render_stateful_widget(create_scrollbar(), scroll_area, &mut lib_list.scroll_state);
     frame.render_widget(create_topic_footer_info(), info_area);
     frame.render_widget(create_footer_selected_topic(lib_list), item_area);
+    if let Some(confirmation) = &lib_list.confirmation {
+        let area = centered_area(frame.area(), 70, 10);
+        frame.render_widget(Clear, area);
+        frame.render_widget(create_confirmation_modal(confirmation), area);
+    }
+}
+
+fn centered_area(area: Rect, percent_x: u16, height: u16) -> Rect {
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::horizontal([Constraint::Percentage(percent_x)])
+        .flex(Flex::Center)
+        .areas(area);
+    area
+}
+
+fn create_confirmation_modal(confirmation: &Confirmation) -> Paragraph<'static> {
+    let text = format!(
+        "$ {}\nRisk: {}\n\nType {} and press ENTER to execute, (ESC) cancel\n> {}",
+        confirmation.command,
+        confirmation.reasons.join(", "),
+        confirmation.expected,
+        confirmation.input
+    );
+    Paragraph::new(text)
+        .style(Style::new().fg(Color::White).bg(Color::Black))
+        .wrap(Wrap { trim: false })
+        .block(
+            Block::bordered()
+                .title(" ⚠ Dangerous command ")
+                .border_type(ratatui::widgets::BorderType::Double)
+                .border_style(Style::new().fg(Color::Red))
+                .padding(Padding::horizontal(1)),
+        )
 }
 
 fn render_all_url_ui_blocks(library_list: &mut LibraryUrlList, frame: &mut Frame) {
@@ -213,7 +395,10 @@ fn render_all_url_ui_blocks(library_list: &mut LibraryUrlList, frame: &mut Frame
         Layout::vertical([Constraint::Percentage(90), Constraint::Length(3)]).areas(main_area);
 
     let (table, lib_list) = create_url_table(library_list);
-    frame.render_widget(create_title(), header_area);
+    frame.render_widget(
+        create_title(lib_list.profile.as_deref(), lib_list.tag_filter.as_deref()),
+        header_area,
+    );
     frame.render_stateful_widget(table, list_area, &mut lib_list.state);
     let scroll_area = list_area.inner(Margin {
         vertical: 1,
@@ -224,10 +409,16 @@ fn render_all_url_ui_blocks(library_list: &mut LibraryUrlList, frame: &mut Frame
 }
 
 fn create_footer_selected_topic(library_list: &LibraryList) -> Paragraph<'static> {
-    let info = if let Some(index) = library_list.state.selected() {
-        format!("$ {}", library_list.topics[index].command)
-    } else {
-        "--".to_string()
+    let info = match library_list.selected_row() {
+        Some(TopicRow::Topic(index)) => {
+            let topic = &library_list.topics[index];
+            format!("$ {}{}", topic.command, context_text(topic))
+        }
+        Some(TopicRow::Step(index, step)) => {
+            let topic = &library_list.topics[index];
+            format!("$ {}{}", topic.steps[step].command, context_text(topic))
+        }
+        None => "--".to_string(),
     };
     let block = Block::new().padding(Padding::horizontal(1));
     Paragraph::new(info).fg(Color::LightGreen).block(block)
@@ -237,18 +428,100 @@ pub fn display_text(text: &str) {
     println!("{}", text.to_string().bright_blue());
 }
 
+pub fn display_error(error: &TermnoteError) {
+    eprintln!("{}", format!("Error: {}", error).bright_red());
+}
+
+pub fn display_warning(text: &str) {
+    eprintln!("{}", format!("Warning: {}", text).bright_yellow());
+}
+
+/// Asks for a line of input on the terminal, `None` once the input is closed.
+pub fn prompt_input(question: &str) -> Result<Option<String>, TermnoteError> {
+    print!("{} ", question.bright_yellow());
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer)? == 0 {
+        println!();
+        return Ok(None);
+    }
+    Ok(Some(answer.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Asks a yes/no question on the terminal, anything but "y" or "yes" declines.
+pub fn prompt_confirmation(question: &str) -> Result<bool, TermnoteError> {
+    print!("{} [y/N] ", question.bright_yellow());
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim().to_lowercase();
+    Ok(answer == "y" || answer == "yes")
+}
+
 struct LibraryUrlList {
+    /// Links shown, those of `all_links` carrying the `tag_filter`.
     links: Vec<Link>,
+    all_links: Vec<Link>,
+    tags: Vec<String>,
+    tag_filter: Option<String>,
     state: TableState,
     scroll_state: ScrollbarState,
-    longest_item_lens: (u16, u16),
+    longest_item_lens: (u16, u16, u16),
+    profile: Option<String>,
 }
 
 struct LibraryList {
+    /// Topics shown, those of `all_topics` carrying the `tag_filter`.
     topics: Vec<Topic>,
+    all_topics: Vec<Topic>,
+    /// Table rows, the steps of expanded topics following their topic.
+    rows: Vec<TopicRow>,
+    /// Ids of the topics whose steps are shown.
+    expanded: HashSet<String>,
+    /// Asked for before executing a dangerous command.
+    confirmation: Option<Confirmation>,
+    tags: Vec<String>,
+    tag_filter: Option<String>,
     state: TableState,
     scroll_state: ScrollbarState,
-    longest_item_lens: (u16, u16, u16),
+    longest_item_lens: (u16, u16, u16, u16),
+    profile: Option<String>,
+}
+
+/// Typed confirmation of a dangerous command, shown as a modal.
+struct Confirmation {
+    command: String,
+    reasons: Vec<String>,
+    /// Text to type to confirm, the id of the topic.
+    expected: String,
+    input: String,
+}
+
+/// Row of the topic table, indexing into `LibraryList::topics`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TopicRow {
+    Topic(usize),
+    /// Topic and step index.
+    Step(usize, usize),
+}
+
+impl LibraryList {
+    fn selected_row(&self) -> Option<TopicRow> {
+        self.state
+            .selected()
+            .and_then(|index| self.rows.get(index).copied())
+    }
+
+    fn refresh_rows(&mut self) {
+        self.rows = Vec::new();
+        for (index, topic) in self.topics.iter().enumerate() {
+            self.rows.push(TopicRow::Topic(index));
+            if self.expanded.contains(&topic.id) {
+                self.rows
+                    .extend((0..topic.steps.len()).map(|step| TopicRow::Step(index, step)));
+            }
+        }
+    }
 }
 
 pub enum MenuEvent {
@@ -261,34 +534,41 @@ pub struct TerminalUrlUI {
     exit: bool,
     library_list: LibraryUrlList,
     item_height: usize,
+    pub selected_id: String,
     pub selected_url: String,
 }
 
 impl TerminalUrlUI {
     pub fn new(links: Vec<Link>) -> Self {
         let item_height: usize = 1;
-        let scroll_state: ScrollbarState = if links.len() > 1 {
-            ScrollbarState::new((links.len() - 1) * item_height)
-        } else {
-            ScrollbarState::default()
-        };
         Self {
             exit: false,
             library_list: LibraryUrlList {
                 links: links.clone(),
+                tags: collect_tags(links.iter().flat_map(|link| &link.tags)),
+                all_links: links.clone(),
+                tag_filter: None,
                 state: TableState::default(),
-                scroll_state,
+                scroll_state: create_scroll_state(links.len(), item_height),
                 longest_item_lens: calc_link_len_constraint(&links),
+                profile: None,
             },
             item_height,
+            selected_id: "".to_string(),
             selected_url: "".to_string(),
         }
     }
 
+    /// Shows the profile name in the header.
+    pub fn with_profile(mut self, profile: Option<String>) -> Self {
+        self.library_list.profile = profile;
+        self
+    }
+
     pub fn menu_loop(
         &mut self,
         terminal: &mut Terminal<CrosstermBackend<Stdout>>,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), TermnoteError> {
         while !self.exit {
             terminal.draw(|frame| render_all_url_ui_blocks(&mut self.library_list, frame))?;
             self.handle_events()?;
@@ -296,12 +576,13 @@ impl TerminalUrlUI {
         Ok(())
     }
 
-    fn handle_events(&mut self) -> Result<(), Box<dyn Error>> {
+    fn handle_events(&mut self) -> Result<(), TermnoteError> {
         match event::read()? {
             Event::Key(event) if event.kind == KeyEventKind::Press => match event.code {
                 KeyCode::Char('q') => {
                     self.exit = true;
                 }
+                KeyCode::Char('t') => self.cycle_tag_filter(),
                 KeyCode::Up => self.select_previous(),
                 KeyCode::Down => self.select_next(),
                 KeyCode::Enter => {
@@ -316,6 +597,9 @@ impl TerminalUrlUI {
     }
 
     fn select_next(&mut self) {
+        if self.library_list.links.is_empty() {
+            return;
+        }
         let index = match self.library_list.state.selected() {
             Some(idx) => {
                 if idx >= self.library_list.links.len() - 1 {
@@ -334,6 +618,9 @@ impl TerminalUrlUI {
     }
 
     fn select_previous(&mut self) {
+        if self.library_list.links.is_empty() {
+            return;
+        }
         let index = match self.library_list.state.selected() {
             Some(idx) => {
                 if idx == 0 {
@@ -351,8 +638,27 @@ impl TerminalUrlUI {
             .position(index * self.item_height);
     }
 
+    /// Shows only the links with the next tag, or all links after the last tag.
+    fn cycle_tag_filter(&mut self) {
+        let list = &mut self.library_list;
+        list.tag_filter = next_tag_filter(&list.tags, list.tag_filter.as_ref());
+        list.links = list
+            .all_links
+            .iter()
+            .filter(|link| {
+                list.tag_filter
+                    .as_ref()
+                    .is_none_or(|tag| link.tags.contains(tag))
+            })
+            .cloned()
+            .collect();
+        list.state.select(None);
+        list.scroll_state = create_scroll_state(list.links.len(), self.item_height);
+    }
+
     fn handle_selected(&mut self) {
         if let Some(index) = &self.library_list.state.selected() {
+            self.selected_id = self.library_list.links[*index].id.to_string();
             self.selected_url = self.library_list.links[*index].url.to_string();
         };
     }
@@ -362,36 +668,52 @@ pub struct TerminalUI {
     exit: bool,
     library_list: LibraryList,
     item_height: usize,
+    pub selected_id: String,
     pub selected_cmd: String,
+    /// Step of the selected topic, `None` if the topic itself was selected.
+    pub selected_step: Option<usize>,
+    /// Whether the execution of a dangerous command was confirmed.
+    pub confirmed: bool,
     pub event: MenuEvent,
 }
 
 impl TerminalUI {
     pub fn new(topics: Vec<Topic>) -> Self {
         let item_height: usize = 1;
-        let scroll_state: ScrollbarState = if topics.len() > 1 {
-            ScrollbarState::new((topics.len() - 1) * item_height)
-        } else {
-            ScrollbarState::default()
-        };
         Self {
             exit: false,
             library_list: LibraryList {
                 topics: topics.clone(),
+                rows: (0..topics.len()).map(TopicRow::Topic).collect(),
+                expanded: HashSet::new(),
+                confirmation: None,
+                tags: collect_tags(topics.iter().flat_map(|topic| &topic.tags)),
+                all_topics: topics.clone(),
+                tag_filter: None,
                 state: TableState::default(),
-                scroll_state,
+                scroll_state: create_scroll_state(topics.len(), item_height),
                 longest_item_lens: calc_topic_len_constraint(&topics),
+                profile: None,
             },
             item_height,
+            selected_id: "".to_string(),
             selected_cmd: "".to_string(),
+            selected_step: None,
+            confirmed: false,
             event: MenuEvent::None,
         }
     }
 
+    /// Shows the profile name in the header.
+    pub fn with_profile(mut self, profile: Option<String>) -> Self {
+        self.library_list.profile = profile;
+        self
+    }
+
     pub fn menu_loop(
         &mut self,
         terminal: &mut Terminal<CrosstermBackend<Stdout>>,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<(), TermnoteError> {
         while !self.exit {
             terminal.draw(|frame| render_all_topic_ui_blocks(&mut self.library_list, frame))?;
             self.handle_events()?;
@@ -399,20 +721,22 @@ impl TerminalUI {
         Ok(())
     }
 
-    fn handle_events(&mut self) -> Result<(), Box<dyn Error>> {
+    fn handle_events(&mut self) -> Result<(), TermnoteError> {
+        if self.library_list.confirmation.is_some() {
+            return self.handle_confirmation_events();
+        }
         match event::read()? {
             Event::Key(event) if event.kind == KeyEventKind::Press => match event.code {
                 KeyCode::Char('q') => {
                     self.event = MenuEvent::None;
                     self.exit = true;
                 }
-                KeyCode::Char('e') => {
-                    self.event = MenuEvent::Execute;
-                    self.handle_selected();
-                    self.exit = true;
-                }
+                KeyCode::Char('e') => self.request_execution(),
+                KeyCode::Char('t') => self.cycle_tag_filter(),
                 KeyCode::Up => self.select_previous(),
                 KeyCode::Down => self.select_next(),
+                KeyCode::Right | KeyCode::Char(' ') => self.expand_selected(),
+                KeyCode::Left => self.collapse_selected(),
                 KeyCode::Enter => {
                     self.event = MenuEvent::Display;
                     self.handle_selected();
@@ -426,9 +750,12 @@ impl TerminalUI {
     }
 
     fn select_next(&mut self) {
+        if self.library_list.rows.is_empty() {
+            return;
+        }
         let index = match self.library_list.state.selected() {
             Some(idx) => {
-                if idx >= self.library_list.topics.len() - 1 {
+                if idx >= self.library_list.rows.len() - 1 {
                     0
                 } else {
                     idx + 1
@@ -444,10 +771,13 @@ impl TerminalUI {
     }
 
     fn select_previous(&mut self) {
+        if self.library_list.rows.is_empty() {
+            return;
+        }
         let index = match self.library_list.state.selected() {
             Some(idx) => {
                 if idx == 0 {
-                    self.library_list.topics.len() - 1
+                    self.library_list.rows.len() - 1
                 } else {
                     idx - 1
                 }
@@ -461,9 +791,132 @@ impl TerminalUI {
             .position(index * self.item_height);
     }
 
-    fn handle_selected(&mut self) {
-        if let Some(index) = &self.library_list.state.selected() {
-            self.selected_cmd = self.library_list.topics[*index].command.to_string();
+    /// Shows only the topics with the next tag, or all topics after the last tag.
+    fn cycle_tag_filter(&mut self) {
+        let list = &mut self.library_list;
+        list.tag_filter = next_tag_filter(&list.tags, list.tag_filter.as_ref());
+        list.topics = list
+            .all_topics
+            .iter()
+            .filter(|topic| {
+                list.tag_filter
+                    .as_ref()
+                    .is_none_or(|tag| topic.tags.contains(tag))
+            })
+            .cloned()
+            .collect();
+        list.refresh_rows();
+        list.state.select(None);
+        list.scroll_state = create_scroll_state(list.rows.len(), self.item_height);
+    }
+
+    fn handle_confirmation_events(&mut self) -> Result<(), TermnoteError> {
+        let Some(confirmation) = &mut self.library_list.confirmation else {
+            return Ok(());
+        };
+        match event::read()? {
+            Event::Key(event) if event.kind == KeyEventKind::Press => match event.code {
+                KeyCode::Esc => self.library_list.confirmation = None,
+                KeyCode::Backspace => {
+                    confirmation.input.pop();
+                }
+                KeyCode::Char(c) => confirmation.input.push(c),
+                KeyCode::Enter if confirmation.input.trim() == confirmation.expected => {
+                    self.library_list.confirmation = None;
+                    self.confirmed = true;
+                    self.execute_selected();
+                }
+                KeyCode::Enter => confirmation.input.clear(),
+                _ => {}
+            },
+            _ => {}
+        };
+        Ok(())
+    }
+
+    /// Executes the selected command, asking for a typed confirmation first
+    /// if it is dangerous.
+    fn request_execution(&mut self) {
+        let list = &self.library_list;
+        let (topic, command) = match list.selected_row() {
+            Some(TopicRow::Topic(index)) => {
+                let topic = &list.topics[index];
+                let command = if topic.steps.is_empty() {
+                    topic.command.clone()
+                } else {
+                    Step::chain(&topic.steps)
+                };
+                (topic, command)
+            }
+            Some(TopicRow::Step(index, step)) => {
+                let topic = &list.topics[index];
+                (topic, topic.steps[step].command.clone())
+            }
+            None => return self.execute_selected(),
         };
+        let reasons = danger_reasons(topic.dangerous, &command);
+        if reasons.is_empty() {
+            return self.execute_selected();
+        }
+        self.library_list.confirmation = Some(Confirmation {
+            expected: topic.id.clone(),
+            command,
+            reasons,
+            input: String::new(),
+        });
+    }
+
+    fn execute_selected(&mut self) {
+        self.event = MenuEvent::Execute;
+        self.handle_selected();
+        self.exit = true;
+    }
+
+    /// Shows the steps of the selected topic below it.
+    fn expand_selected(&mut self) {
+        let list = &mut self.library_list;
+        if let Some(TopicRow::Topic(index)) = list.selected_row() {
+            if !list.topics[index].steps.is_empty() {
+                list.expanded.insert(list.topics[index].id.clone());
+                list.refresh_rows();
+                list.scroll_state = create_scroll_state(list.rows.len(), self.item_height);
+            }
+        }
+    }
+
+    /// Hides the steps of the selected topic, or of the topic of the selected step.
+    fn collapse_selected(&mut self) {
+        let list = &mut self.library_list;
+        let index = match list.selected_row() {
+            Some(TopicRow::Topic(index)) | Some(TopicRow::Step(index, _)) => index,
+            None => return,
+        };
+        if list.expanded.remove(&list.topics[index].id) {
+            list.refresh_rows();
+            let row = list
+                .rows
+                .iter()
+                .position(|row| *row == TopicRow::Topic(index));
+            list.state.select(row);
+            list.scroll_state = create_scroll_state(list.rows.len(), self.item_height)
+                .position(row.unwrap_or(0) * self.item_height);
+        }
+    }
+
+    fn handle_selected(&mut self) {
+        let list = &self.library_list;
+        match list.selected_row() {
+            Some(TopicRow::Topic(index)) => {
+                self.selected_id = list.topics[index].id.to_string();
+                self.selected_cmd = list.topics[index].command.to_string();
+                self.selected_step = None;
+            }
+            Some(TopicRow::Step(index, step)) => {
+                self.selected_id = list.topics[index].id.to_string();
+                self.selected_cmd = list.topics[index].steps[step].command.to_string();
+                self.selected_step = Some(step);
+            }
+            None => {}
+        }
     }
 }
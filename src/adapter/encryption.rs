@@ -0,0 +1,409 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use argon2::Argon2;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::adapter::file_utils::{sibling_path, write_atomic};
+use crate::TermnoteError;
+
+/// Passphrase to unlock an encrypted library.
+pub const KEY_ENV: &str = "TERMNOTE_KEY";
+/// File whose contents unlock an encrypted library.
+pub const KEYFILE_ENV: &str = "TERMNOTE_KEYFILE";
+
+const SCHEME: &str = "xchacha20poly1305-argon2id";
+const SALT_LEN: usize = 16;
+const KEY_LEN: usize = 32;
+
+/// Where the secret unlocking an encrypted library comes from.
+#[derive(Clone, Debug)]
+pub enum KeySource {
+    Passphrase(String),
+    Keyfile(PathBuf),
+    /// Asks for a passphrase on the terminal.
+    Prompt,
+}
+
+impl KeySource {
+    /// Reads the secret, asking twice when prompting for a new passphrase.
+    pub fn secret(&self, confirm: bool) -> Result<Zeroizing<Vec<u8>>, TermnoteError> {
+        let secret = match self {
+            KeySource::Passphrase(passphrase) => Zeroizing::new(passphrase.as_bytes().to_vec()),
+            KeySource::Keyfile(path) => Zeroizing::new(fs::read(path).map_err(|e| {
+                TermnoteError::Encryption(format!(
+                    "Could not read keyfile {}: {}",
+                    path.display(),
+                    e
+                ))
+            })?),
+            KeySource::Prompt => Self::prompt(confirm)?,
+        };
+        if secret.is_empty() {
+            return Err(TermnoteError::Encryption(
+                "The passphrase or keyfile is empty".to_string(),
+            ));
+        }
+        Ok(secret)
+    }
+
+    fn prompt(confirm: bool) -> Result<Zeroizing<Vec<u8>>, TermnoteError> {
+        let no_terminal = |e: std::io::Error| {
+            TermnoteError::Encryption(format!(
+                "Could not read the passphrase ({}), set {} or {}",
+                e, KEY_ENV, KEYFILE_ENV
+            ))
+        };
+        let passphrase = Zeroizing::new(
+            rpassword::prompt_password("Library passphrase: ").map_err(no_terminal)?,
+        );
+        if confirm {
+            let repeated = Zeroizing::new(
+                rpassword::prompt_password("Repeat the passphrase: ").map_err(no_terminal)?,
+            );
+            if passphrase != repeated {
+                return Err(TermnoteError::Validation(
+                    "The passphrases do not match".to_string(),
+                ));
+            }
+        }
+        Ok(Zeroizing::new(passphrase.as_bytes().to_vec()))
+    }
+}
+
+/// Encrypted file contents, stored as JSON with base64 encoded binary fields.
+#[derive(Deserialize, Serialize)]
+struct Envelope {
+    encryption: String,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Envelope {
+    fn parse(contents: &[u8]) -> Option<Self> {
+        serde_json::from_slice(contents).ok()
+    }
+}
+
+/// Whether the contents were written by [`Cipher::seal`].
+pub fn is_encrypted(contents: &[u8]) -> bool {
+    Envelope::parse(contents).is_some()
+}
+
+/// File next to an encrypted library recording that it is encrypted, so that a
+/// library replaced by a plaintext one is refused instead of being used unencrypted.
+pub fn encryption_marker(path: &Path) -> PathBuf {
+    sibling_path(path, ".encrypted")
+}
+
+/// Creates the marker of the library at `path` when it gets encrypted, or
+/// removes it when it gets decrypted.
+pub fn mark_encrypted(path: &Path, encrypted: bool) -> Result<(), TermnoteError> {
+    let marker = encryption_marker(path);
+    if encrypted {
+        write_atomic(
+            &marker,
+            b"The library next to this file is encrypted, termnote refuses to read it in plaintext.\n",
+        )?;
+    } else if marker.exists() {
+        fs::remove_file(marker)?;
+    }
+    Ok(())
+}
+
+fn not_encrypted(path: &Path) -> TermnoteError {
+    TermnoteError::Encryption(format!(
+        "{} is marked as encrypted but is not, if it was decrypted or removed on purpose remove {}",
+        path.display(),
+        encryption_marker(path).display()
+    ))
+}
+
+/// Authenticated encryption of library files. The key is derived from the
+/// secret with Argon2id; the salt is kept across writes so that the costly
+/// derivation runs once per invocation, while every write uses a fresh nonce.
+#[derive(Clone)]
+pub struct Cipher {
+    secret: Zeroizing<Vec<u8>>,
+    salt: [u8; SALT_LEN],
+    key: Zeroizing<[u8; KEY_LEN]>,
+}
+
+impl Cipher {
+    /// Creates a cipher with a new random salt, for encrypting a library.
+    pub fn new(secret: Zeroizing<Vec<u8>>) -> Result<Self, TermnoteError> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(secret, salt)
+    }
+
+    /// Returns the cipher for the file at `path` if it is encrypted, reading
+    /// the secret from `source`. A library marked as encrypted must be encrypted.
+    pub fn unlock(path: &Path, source: &KeySource) -> Result<Option<Self>, TermnoteError> {
+        let marked = encryption_marker(path).exists();
+        let contents = if path.exists() {
+            fs::read(path)?
+        } else {
+            Vec::new()
+        };
+        let Some(envelope) = Envelope::parse(&contents) else {
+            return if marked {
+                Err(not_encrypted(path))
+            } else {
+                Ok(None)
+            };
+        };
+        let cipher = Self::derive(source.secret(false)?, decode_salt(&envelope)?)?;
+        // fail early on a wrong passphrase
+        cipher.open(&contents)?;
+        Ok(Some(cipher))
+    }
+
+    fn derive(secret: Zeroizing<Vec<u8>>, salt: [u8; SALT_LEN]) -> Result<Self, TermnoteError> {
+        let mut key = Zeroizing::new([0u8; KEY_LEN]);
+        Argon2::default()
+            .hash_password_into(&secret, &salt, key.as_mut())
+            .map_err(|e| TermnoteError::Encryption(format!("Could not derive the key: {}", e)))?;
+        Ok(Self { secret, salt, key })
+    }
+
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>, TermnoteError> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = XChaCha20Poly1305::new(self.key.as_ref().into())
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| TermnoteError::Encryption("Could not encrypt the library".to_string()))?;
+        let envelope = Envelope {
+            encryption: SCHEME.to_string(),
+            salt: STANDARD.encode(self.salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        Ok(serde_json::to_vec(&envelope)?)
+    }
+
+    /// Decrypts sealed contents, refusing plaintext.
+    pub fn open(&self, contents: &[u8]) -> Result<Vec<u8>, TermnoteError> {
+        let Some(envelope) = Envelope::parse(contents) else {
+            return Err(TermnoteError::Encryption(
+                "The contents are not encrypted although the library is".to_string(),
+            ));
+        };
+        if envelope.encryption != SCHEME {
+            return Err(TermnoteError::Encryption(format!(
+                "Unsupported encryption scheme '{}'",
+                envelope.encryption
+            )));
+        }
+        let salt = decode_salt(&envelope)?;
+        // written with another salt, e.g. re-encrypted by another invocation
+        let rederived;
+        let cipher = if salt == self.salt {
+            self
+        } else {
+            rederived = Self::derive(self.secret.clone(), salt)?;
+            &rederived
+        };
+        let nonce = decode(&envelope.nonce)?;
+        if nonce.len() != 24 {
+            return Err(damaged());
+        }
+        XChaCha20Poly1305::new(cipher.key.as_ref().into())
+            .decrypt(
+                XNonce::from_slice(&nonce),
+                decode(&envelope.ciphertext)?.as_slice(),
+            )
+            .map_err(|_| {
+                TermnoteError::Encryption(
+                    "Could not decrypt the library, wrong passphrase or keyfile".to_string(),
+                )
+            })
+    }
+}
+
+/// Files derived from the library, like its journal or backups, which are
+/// encrypted together with it.
+#[derive(Clone, Default)]
+pub struct SealedFiles {
+    cipher: Option<Cipher>,
+}
+
+impl SealedFiles {
+    pub fn new(cipher: Option<Cipher>) -> Self {
+        Self { cipher }
+    }
+
+    /// Reads a file, which must be encrypted exactly when there is a cipher.
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>, TermnoteError> {
+        let contents = fs::read(path)?;
+        match &self.cipher {
+            Some(_) if !is_encrypted(&contents) => Err(TermnoteError::Encryption(format!(
+                "{} is not encrypted but the library is",
+                path.display()
+            ))),
+            Some(cipher) => cipher.open(&contents),
+            None if is_encrypted(&contents) => Err(TermnoteError::Encryption(format!(
+                "{} is encrypted but the library is not",
+                path.display()
+            ))),
+            None => Ok(contents),
+        }
+    }
+
+    pub fn write(&self, path: &Path, contents: &[u8]) -> Result<(), TermnoteError> {
+        match &self.cipher {
+            Some(cipher) => write_atomic(path, &cipher.seal(contents)?)?,
+            None => write_atomic(path, contents)?,
+        }
+        Ok(())
+    }
+
+    /// Reads the JSON file at `path`, `None` if it does not exist.
+    pub fn read_json<T: DeserializeOwned>(&self, path: &Path) -> Result<Option<T>, TermnoteError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        serde_json::from_slice(&self.read(path)?)
+            .map(Some)
+            .map_err(|e| TermnoteError::Parse(format!("{} in {}", e, path.display())))
+    }
+
+    pub fn write_json<T: Serialize>(&self, path: &Path, value: &T) -> Result<(), TermnoteError> {
+        self.write(path, &serde_json::to_vec(value)?)
+    }
+
+    /// Rewrites the existing files among `paths` encrypted with `cipher`, or
+    /// in plaintext for `None`. Every file is read before any is written, so
+    /// a file which cannot be decrypted leaves all of them untouched.
+    pub fn set_cipher(
+        &mut self,
+        paths: &[PathBuf],
+        cipher: Option<Cipher>,
+    ) -> Result<(), TermnoteError> {
+        let contents = paths
+            .iter()
+            .filter(|path| path.exists())
+            .map(|path| Ok((path, self.read(path)?)))
+            .collect::<Result<Vec<_>, TermnoteError>>()?;
+        self.cipher = cipher;
+        for (path, plaintext) in contents {
+            self.write(path, &plaintext)?;
+        }
+        Ok(())
+    }
+}
+
+fn decode(field: &str) -> Result<Vec<u8>, TermnoteError> {
+    STANDARD.decode(field).map_err(|_| damaged())
+}
+
+fn decode_salt(envelope: &Envelope) -> Result<[u8; SALT_LEN], TermnoteError> {
+    decode(&envelope.salt)?.try_into().map_err(|_| damaged())
+}
+
+fn damaged() -> TermnoteError {
+    TermnoteError::Encryption("The encrypted library is damaged".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ObjectDB, Repository, TermnoteError};
+
+    fn passphrase(secret: &str) -> KeySource {
+        KeySource::Passphrase(secret.to_string())
+    }
+
+    #[test]
+    fn sealed_contents_open_with_the_same_secret() {
+        let cipher = Cipher::new(passphrase("secret").secret(false).unwrap()).unwrap();
+        let sealed = cipher.seal(b"library").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert_ne!(cipher.seal(b"library").unwrap(), sealed);
+        assert_eq!(cipher.open(&sealed).unwrap(), b"library");
+
+        let other = Cipher::new(passphrase("other").secret(false).unwrap()).unwrap();
+        assert!(matches!(
+            other.open(&sealed),
+            Err(TermnoteError::Encryption(_))
+        ));
+    }
+
+    #[test]
+    fn encrypted_library_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.json");
+        fs::write(&path, r#"{"topics": [{"title": "List", "command": "ls"}]}"#).unwrap();
+        let mut db = ObjectDB::init(&path).unwrap();
+        let cipher = Cipher::new(passphrase("secret").secret(false).unwrap()).unwrap();
+        db.set_cipher(Some(cipher)).unwrap();
+
+        let contents = fs::read(&path).unwrap();
+        assert!(is_encrypted(&contents));
+        assert!(!String::from_utf8_lossy(&contents).contains("List"));
+        assert!(matches!(
+            ObjectDB::init(&path),
+            Err(TermnoteError::Encryption(_))
+        ));
+        assert!(matches!(
+            Cipher::unlock(&path, &passphrase("wrong")),
+            Err(TermnoteError::Encryption(_))
+        ));
+
+        let cipher = Cipher::unlock(&path, &passphrase("secret")).unwrap();
+        let db = ObjectDB::init_with_cipher(&path, cipher).unwrap();
+        assert_eq!(db.library.topics[0].title, "List");
+    }
+
+    #[test]
+    fn plaintext_is_refused_once_the_library_was_encrypted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.json");
+        let mut db = ObjectDB::init(&path).unwrap();
+        let plaintext = fs::read(&path).unwrap();
+        let cipher = Cipher::new(passphrase("secret").secret(false).unwrap()).unwrap();
+        db.set_cipher(Some(cipher.clone())).unwrap();
+        assert!(encryption_marker(&path).exists());
+
+        fs::write(&path, &plaintext).unwrap();
+        assert!(matches!(
+            Cipher::unlock(&path, &passphrase("secret")),
+            Err(TermnoteError::Encryption(_))
+        ));
+        assert!(matches!(
+            cipher.open(&plaintext),
+            Err(TermnoteError::Encryption(_))
+        ));
+        assert!(matches!(
+            SealedFiles::new(Some(cipher)).read(&path),
+            Err(TermnoteError::Encryption(_))
+        ));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            Cipher::unlock(&path, &passphrase("secret")),
+            Err(TermnoteError::Encryption(_))
+        ));
+
+        let mut db = ObjectDB::init(&path).unwrap();
+        db.set_cipher(None).unwrap();
+        assert!(!encryption_marker(&path).exists());
+        assert!(Cipher::unlock(&path, &passphrase("secret"))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn empty_secrets_are_refused() {
+        assert!(matches!(
+            passphrase("").secret(false),
+            Err(TermnoteError::Encryption(_))
+        ));
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::adapter::encryption::SealedFiles;
+use crate::adapter::file_utils::{sibling_path, FileLock};
+use crate::adapter::{Cipher, Shell};
+use crate::TermnoteError;
+
+/// A command executed through termnote.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Execution {
+    /// Sequence number the execution is referred to by, e.g. in `tn history rerun`.
+    pub number: u64,
+    pub topic_id: String,
+    /// Title of the topic at the time, which may have been removed since.
+    pub title: String,
+    /// Command as executed, with its placeholders filled in.
+    pub command: String,
+    pub cwd: PathBuf,
+    #[serde(default)]
+    pub shell: Option<Shell>,
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub exit_code: i32,
+    #[serde(default)]
+    pub timed_out: bool,
+    /// Last part of the output, `None` unless it was captured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Whether the beginning of the output was cut off.
+    #[serde(default)]
+    pub output_truncated: bool,
+}
+
+impl Execution {
+    pub fn success(&self) -> bool {
+        self.exit_code == 0 && !self.timed_out
+    }
+}
+
+/// Persistent history of executed commands, bounded to the `size` latest ones.
+pub struct ExecutionLog {
+    path: PathBuf,
+    size: usize,
+    files: SealedFiles,
+}
+
+impl ExecutionLog {
+    pub fn new(path: &Path, size: usize) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            size,
+            files: SealedFiles::default(),
+        }
+    }
+
+    pub fn with_cipher(mut self, cipher: Option<Cipher>) -> Self {
+        self.files = SealedFiles::new(cipher);
+        self
+    }
+
+    pub fn set_cipher(&mut self, cipher: Option<Cipher>) -> Result<(), TermnoteError> {
+        self.files
+            .set_cipher(std::slice::from_ref(&self.path), cipher)
+    }
+
+    /// Lists the executions, oldest first.
+    pub fn read(&self) -> Result<Vec<Execution>, TermnoteError> {
+        Ok(self.files.read_json(&self.path)?.unwrap_or_default())
+    }
+
+    /// Adds an execution under the next sequence number and drops the oldest
+    /// ones beyond the configured size.
+    pub fn record(&self, mut execution: Execution) -> Result<(), TermnoteError> {
+        if self.size == 0 {
+            return Ok(());
+        }
+        // several commands may finish at the same time
+        let _lock = FileLock::acquire(&sibling_path(&self.path, ".lock"))?;
+        // recorded executions are only dropped when the history is not valid
+        // JSON, not when it cannot be read or decrypted
+        let mut executions = match self.read() {
+            Err(TermnoteError::Parse(_)) => Vec::new(),
+            executions => executions?,
+        };
+        execution.number = executions.last().map_or(1, |last| last.number + 1);
+        executions.push(execution);
+        let overflow = executions.len().saturating_sub(self.size);
+        executions.drain(..overflow);
+        self.files.write_json(&self.path, &executions)
+    }
+
+    pub fn find(&self, number: u64) -> Result<Execution, TermnoteError> {
+        self.read()?
+            .into_iter()
+            .find(|execution| execution.number == number)
+            .ok_or_else(|| {
+                TermnoteError::NotFound(format!(
+                    "Could not find execution {} in the history",
+                    number
+                ))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn execution(command: &str, exit_code: i32) -> Execution {
+        Execution {
+            number: 0,
+            topic_id: "a1".to_string(),
+            title: "Test".to_string(),
+            command: command.to_string(),
+            cwd: PathBuf::from("/tmp"),
+            shell: None,
+            env: BTreeMap::new(),
+            started_at: Utc::now(),
+            ended_at: Utc::now(),
+            exit_code,
+            timed_out: false,
+            output: None,
+            output_truncated: false,
+        }
+    }
+
+    fn commands(log: &ExecutionLog) -> Vec<(u64, String)> {
+        log.read()
+            .unwrap()
+            .into_iter()
+            .map(|execution| (execution.number, execution.command))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_latest_executions_numbered() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = ExecutionLog::new(&dir.path().join("db.history.json"), 2);
+        assert!(log.read().unwrap().is_empty());
+        for command in ["a", "b", "c"] {
+            log.record(execution(command, 0)).unwrap();
+        }
+        assert_eq!(commands(&log), [(2, "b".to_string()), (3, "c".to_string())]);
+        assert_eq!(log.find(3).unwrap().command, "c");
+        assert!(matches!(log.find(1), Err(TermnoteError::NotFound(_))));
+        assert!(!execution("x", 1).success());
+    }
+
+    #[test]
+    fn zero_size_disables_the_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.history.json");
+        ExecutionLog::new(&path, 0)
+            .record(execution("a", 0))
+            .unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn encrypted_history_can_be_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.history.json");
+        let mut log = ExecutionLog::new(&path, 10);
+        log.record(execution("echo secret", 0)).unwrap();
+        let secret = zeroize::Zeroizing::new(b"passphrase".to_vec());
+        log.set_cipher(Some(Cipher::new(secret).unwrap())).unwrap();
+        let contents = fs::read(&path).unwrap();
+        assert!(crate::adapter::is_encrypted(&contents));
+        assert!(!String::from_utf8_lossy(&contents).contains("echo secret"));
+        assert_eq!(commands(&log), [(1, "echo secret".to_string())]);
+    }
+
+    #[test]
+    fn only_an_unparsable_history_is_started_afresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.history.json");
+        let log = ExecutionLog::new(&path, 10);
+        fs::write(&path, "not a history").unwrap();
+        log.record(execution("a", 0)).unwrap();
+        assert_eq!(commands(&log), [(1, "a".to_string())]);
+
+        let secret = zeroize::Zeroizing::new(b"secret".to_vec());
+        let sealed = Cipher::new(secret).unwrap().seal(b"[]").unwrap();
+        fs::write(&path, &sealed).unwrap();
+        assert!(matches!(
+            log.record(execution("b", 0)),
+            Err(TermnoteError::Encryption(_))
+        ));
+        assert_eq!(fs::read(&path).unwrap(), sealed);
+    }
+}
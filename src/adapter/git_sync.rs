@@ -0,0 +1,162 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use crate::adapter::file_utils::write_atomic;
+use crate::adapter::profile::PROFILES_FOLDER;
+use crate::TermnoteError;
+
+/// Machine specific files of the termnote home directory which are not synced.
+const IGNORED_FILES: &str =
+    "backups/\n*.lock\n*.tmp*\n*.bak\n*.damaged-*\n*.encrypted\n*.journal.json\n*.history.json\n";
+
+/// Git repository in the termnote home directory through which the library is
+/// synced, driven by the local `git` binary.
+pub struct GitSync {
+    dir: PathBuf,
+    library_file: PathBuf,
+}
+
+impl GitSync {
+    /// `library_file` is the library database, relative to `dir`.
+    pub fn new(dir: &Path, library_file: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            library_file: library_file.to_path_buf(),
+        }
+    }
+
+    pub fn library_file(&self) -> &Path {
+        &self.library_file
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.dir.join(".git").exists()
+    }
+
+    /// Turns the directory into a git repository, optionally tracking `remote`
+    /// as `origin`, and commits the current state.
+    pub fn init(&self, remote: Option<&str>) -> Result<(), TermnoteError> {
+        fs::create_dir_all(&self.dir)?;
+        if !self.is_initialized() {
+            self.git(&["init", "--quiet"])?;
+        }
+        let ignore_path = self.dir.join(".gitignore");
+        if !ignore_path.exists() {
+            write_atomic(&ignore_path, IGNORED_FILES.as_bytes())?;
+        }
+        if let Some(remote) = remote {
+            self.git(&["remote", "add", "origin", remote])?;
+        }
+        self.commit("Start syncing the termnote library")?;
+        Ok(())
+    }
+
+    /// Commits the changes to the library, the profile libraries and the
+    /// `.gitignore`, returning whether there was any. Other files of the
+    /// directory, e.g. the settings or a keyfile, are never committed.
+    pub fn commit(&self, message: &str) -> Result<bool, TermnoteError> {
+        let mut args = vec!["add", "--all", "--"];
+        let library_file = self.library_file.to_string_lossy();
+        args.push(&library_file);
+        for path in [".gitignore", PROFILES_FOLDER] {
+            if self.dir.join(path).exists() {
+                args.push(path);
+            }
+        }
+        self.git(&args)?;
+        if self.run(&["diff", "--cached", "--quiet"])?.status.success() {
+            return Ok(false);
+        }
+        self.git(&["commit", "--quiet", "-m", message])?;
+        Ok(true)
+    }
+
+    /// Remote tracking branch of the current branch, if one is configured.
+    pub fn upstream(&self) -> Result<Option<String>, TermnoteError> {
+        let output = self.run(&["rev-parse", "--abbrev-ref", "--symbolic-full-name", "@{u}"])?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+        Ok(Some(
+            String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        ))
+    }
+
+    /// Remote tracking branch of the current branch. Without one configured the
+    /// same-named branch of `origin` is tracked if it exists, e.g. when the
+    /// remote was pushed to from another machine.
+    pub fn tracking_branch(&self) -> Result<Option<String>, TermnoteError> {
+        if let Some(upstream) = self.upstream()? {
+            return Ok(Some(upstream));
+        }
+        let branch = self.git(&["rev-parse", "--abbrev-ref", "HEAD"])?;
+        let remote_branch = format!("origin/{}", branch.trim());
+        let output = self.run(&["rev-parse", "--quiet", "--verify", &remote_branch])?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+        self.git(&["branch", "--quiet", "--set-upstream-to", &remote_branch])?;
+        Ok(Some(remote_branch))
+    }
+
+    pub fn is_ancestor(&self, ancestor: &str, revision: &str) -> Result<bool, TermnoteError> {
+        let output = self.run(&["merge-base", "--is-ancestor", ancestor, revision])?;
+        Ok(output.status.success())
+    }
+
+    /// Common ancestor of both revisions, `None` for unrelated histories.
+    pub fn merge_base(&self, first: &str, second: &str) -> Result<Option<String>, TermnoteError> {
+        let output = self.run(&["merge-base", first, second])?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+        Ok(Some(
+            String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        ))
+    }
+
+    /// Library file contents at `revision`, `None` if it did not exist there.
+    pub fn show_library(&self, revision: &str) -> Result<Option<Vec<u8>>, TermnoteError> {
+        let object = format!("{}:./{}", revision, self.library_file.display());
+        let output = self.run(&["show", &object])?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+        Ok(Some(output.stdout))
+    }
+
+    /// Whether a merge was started and waits to be committed.
+    pub fn is_merging(&self) -> Result<bool, TermnoteError> {
+        let output = self.run(&["rev-parse", "--quiet", "--verify", "MERGE_HEAD"])?;
+        Ok(output.status.success())
+    }
+
+    /// Files of an ongoing merge which still have conflicts.
+    pub fn conflicted_files(&self) -> Result<Vec<String>, TermnoteError> {
+        let output = self.git(&["diff", "--name-only", "--diff-filter=U"])?;
+        Ok(output.lines().map(str::to_string).collect())
+    }
+
+    /// Runs git in the directory and returns its output, failing on a non-zero exit.
+    pub fn git(&self, args: &[&str]) -> Result<String, TermnoteError> {
+        let output = self.run(args)?;
+        if !output.status.success() {
+            return Err(TermnoteError::Sync(format!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    fn run(&self, args: &[&str]) -> Result<Output, TermnoteError> {
+        Command::new("git")
+            .arg("-C")
+            .arg(&self.dir)
+            .args(args)
+            .output()
+            .map_err(|e| TermnoteError::Sync(format!("Could not run git: {}", e)))
+    }
+}
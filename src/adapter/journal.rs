@@ -0,0 +1,200 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::adapter::encryption::SealedFiles;
+use crate::adapter::{Cipher, Link, Topic};
+use crate::TermnoteError;
+
+/// State of a single entry before and after an operation, `None` meaning absent.
+#[derive(Deserialize, Serialize, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum Change {
+    Topic {
+        index: usize,
+        before: Option<Topic>,
+        after: Option<Topic>,
+    },
+    Link {
+        index: usize,
+        before: Option<Link>,
+        after: Option<Link>,
+    },
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+pub struct JournalEntry {
+    pub description: String,
+    pub change: Change,
+    /// Project library the change was made in, `None` for the global library.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub library: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+pub struct JournalState {
+    pub undo: Vec<JournalEntry>,
+    pub redo: Vec<JournalEntry>,
+}
+
+/// Persistent undo/redo history of library operations, bounded to `depth` entries.
+pub struct Journal {
+    path: PathBuf,
+    depth: usize,
+    files: SealedFiles,
+}
+
+impl Journal {
+    pub fn new(path: &Path, depth: usize) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            depth,
+            files: SealedFiles::default(),
+        }
+    }
+
+    pub fn with_cipher(mut self, cipher: Option<Cipher>) -> Self {
+        self.files = SealedFiles::new(cipher);
+        self
+    }
+
+    pub fn set_cipher(&mut self, cipher: Option<Cipher>) -> Result<(), TermnoteError> {
+        self.files
+            .set_cipher(std::slice::from_ref(&self.path), cipher)
+    }
+
+    pub fn read(&self) -> Result<JournalState, TermnoteError> {
+        Ok(self.files.read_json(&self.path)?.unwrap_or_default())
+    }
+
+    pub fn write(&self, state: &JournalState) -> Result<(), TermnoteError> {
+        self.files.write_json(&self.path, state)
+    }
+
+    /// Records a new operation, which invalidates everything that could be redone.
+    pub fn record(&self, entry: JournalEntry) -> Result<(), TermnoteError> {
+        if self.depth == 0 {
+            return Ok(());
+        }
+        // a journal in a format this termnote does not understand is started
+        // afresh, one which cannot be read or decrypted must not be replaced
+        let mut state = match self.read() {
+            Err(TermnoteError::Parse(_)) => JournalState::default(),
+            state => state?,
+        };
+        state.undo.push(entry);
+        let overflow = state.undo.len().saturating_sub(self.depth);
+        state.undo.drain(..overflow);
+        state.redo.clear();
+        self.write(&state)
+    }
+
+    pub fn clear(&self) -> Result<(), TermnoteError> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+        self.write(&JournalState::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn entry(description: &str) -> JournalEntry {
+        JournalEntry {
+            description: description.to_string(),
+            change: Change::Topic {
+                index: 0,
+                before: None,
+                after: None,
+            },
+            library: None,
+        }
+    }
+
+    fn descriptions(entries: &[JournalEntry]) -> Vec<&str> {
+        entries
+            .iter()
+            .map(|entry| entry.description.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_latest_operations() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(&dir.path().join("db.journal.json"), 2);
+        assert!(journal.read().unwrap().undo.is_empty());
+        for description in ["a", "b", "c"] {
+            journal.record(entry(description)).unwrap();
+        }
+        let state = journal.read().unwrap();
+        assert_eq!(descriptions(&state.undo), ["b", "c"]);
+        assert!(state.redo.is_empty());
+    }
+
+    #[test]
+    fn recording_drops_what_could_be_redone() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(&dir.path().join("db.journal.json"), 10);
+        journal
+            .write(&JournalState {
+                undo: vec![entry("a")],
+                redo: vec![entry("b")],
+            })
+            .unwrap();
+        journal.record(entry("c")).unwrap();
+        let state = journal.read().unwrap();
+        assert_eq!(descriptions(&state.undo), ["a", "c"]);
+        assert!(state.redo.is_empty());
+
+        journal.clear().unwrap();
+        assert!(journal.read().unwrap().undo.is_empty());
+    }
+
+    #[test]
+    fn zero_depth_disables_the_journal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.journal.json");
+        Journal::new(&path, 0).record(entry("a")).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn encrypted_journal_can_be_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.journal.json");
+        let secret = zeroize::Zeroizing::new(b"secret".to_vec());
+        let cipher = Cipher::new(secret).unwrap();
+        let journal = Journal::new(&path, 10).with_cipher(Some(cipher));
+        journal.record(entry("add topic 'List'")).unwrap();
+        let contents = fs::read(&path).unwrap();
+        assert!(crate::adapter::is_encrypted(&contents));
+        assert!(!String::from_utf8_lossy(&contents).contains("List"));
+        assert_eq!(
+            descriptions(&journal.read().unwrap().undo),
+            ["add topic 'List'"]
+        );
+    }
+
+    #[test]
+    fn only_an_unparsable_journal_is_started_afresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.journal.json");
+        let journal = Journal::new(&path, 10);
+        fs::write(&path, "not a journal").unwrap();
+        journal.record(entry("a")).unwrap();
+        assert_eq!(descriptions(&journal.read().unwrap().undo), ["a"]);
+
+        let secret = zeroize::Zeroizing::new(b"secret".to_vec());
+        let sealed = Cipher::new(secret).unwrap().seal(b"{}").unwrap();
+        fs::write(&path, &sealed).unwrap();
+        assert!(matches!(
+            journal.record(entry("b")),
+            Err(TermnoteError::Encryption(_))
+        ));
+        assert_eq!(fs::read(&path).unwrap(), sealed);
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashSet;
+
+use chrono::Utc;
+use serde_json::{Map, Value};
+
+use crate::adapter::generate_id;
+
+use crate::TermnoteError;
+
+/// Version of the library format written by this build of termnote.
+pub const CURRENT_VERSION: u32 = 7;
+
+/// Upgrade steps, the step at index `n` migrates a document from version `n` to `n + 1`.
+const MIGRATIONS: [fn(&mut Map<String, Value>); CURRENT_VERSION as usize] = [
+    migrate_v0_to_v1,
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+    migrate_v6_to_v7,
+];
+
+/// Upgrades a raw library document to `CURRENT_VERSION` in place.
+///
+/// Returns the version the document had before if any migration was applied
+/// and refuses documents written by a newer termnote.
+pub fn migrate(document: &mut Value) -> Result<Option<u32>, TermnoteError> {
+    let root = document.as_object_mut().ok_or_else(|| {
+        TermnoteError::Parse("expected the library to be a JSON object".to_string())
+    })?;
+    let version = match root.get("version") {
+        None => 0,
+        Some(value) => value
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| TermnoteError::Parse(format!("invalid library version {}", value)))?,
+    };
+    if version > CURRENT_VERSION {
+        return Err(TermnoteError::UnsupportedVersion(version));
+    }
+    if version == CURRENT_VERSION {
+        return Ok(None);
+    }
+    for step in &MIGRATIONS[version as usize..] {
+        step(root);
+    }
+    root.insert("version".to_string(), Value::from(CURRENT_VERSION));
+    Ok(Some(version))
+}
+
+fn entries_mut<'a>(
+    root: &'a mut Map<String, Value>,
+    key: &str,
+) -> impl Iterator<Item = &'a mut Map<String, Value>> {
+    root.get_mut(key)
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_object_mut)
+}
+
+/// Unversioned libraries may lack the `links` list and the topic `category`
+/// and `description` which were added after the first release.
+fn migrate_v0_to_v1(root: &mut Map<String, Value>) {
+    for key in ["topics", "links"] {
+        if !root.get(key).is_some_and(Value::is_array) {
+            root.insert(key.to_string(), Value::Array(Vec::new()));
+        }
+    }
+    for topic in entries_mut(root, "topics") {
+        topic
+            .entry("description")
+            .or_insert_with(|| Value::from(""));
+        topic
+            .entry("category")
+            .or_insert_with(|| Value::from("default"));
+    }
+}
+
+/// Topics and links are addressed by a stable id instead of their position.
+fn migrate_v1_to_v2(root: &mut Map<String, Value>) {
+    for key in ["topics", "links"] {
+        let mut taken: HashSet<String> = entries_mut(root, key)
+            .filter_map(|entry| entry.get("id").and_then(Value::as_str).map(String::from))
+            .collect();
+        for entry in entries_mut(root, key) {
+            if entry.get("id").is_some_and(Value::is_string) {
+                continue;
+            }
+            let id = generate_id(|id| taken.contains(id));
+            taken.insert(id.clone());
+            entry.insert("id".to_string(), Value::from(id));
+        }
+    }
+}
+
+/// Entries track when they were created, changed and used. The creation time
+/// of existing entries is unknown, so the time of the migration is taken.
+fn migrate_v2_to_v3(root: &mut Map<String, Value>) {
+    let now = Value::from(Utc::now().to_rfc3339());
+    for key in ["topics", "links"] {
+        for entry in entries_mut(root, key) {
+            entry.entry("created_at").or_insert_with(|| now.clone());
+            entry.entry("updated_at").or_insert_with(|| now.clone());
+            entry.entry("last_used_at").or_insert(Value::Null);
+            entry.entry("use_count").or_insert_with(|| Value::from(0));
+        }
+    }
+}
+
+/// Topics and links carry tags in addition to the single topic category.
+fn migrate_v3_to_v4(root: &mut Map<String, Value>) {
+    for key in ["topics", "links"] {
+        for entry in entries_mut(root, key) {
+            entry
+                .entry("tags")
+                .or_insert_with(|| Value::Array(Vec::new()));
+        }
+    }
+}
+
+/// Topics may consist of several commands run in order.
+fn migrate_v4_to_v5(root: &mut Map<String, Value>) {
+    for topic in entries_mut(root, "topics") {
+        topic
+            .entry("steps")
+            .or_insert_with(|| Value::Array(Vec::new()));
+    }
+}
+
+/// Topics may set the directory, environment and shell their commands run with.
+fn migrate_v5_to_v6(root: &mut Map<String, Value>) {
+    for topic in entries_mut(root, "topics") {
+        topic.entry("cwd").or_insert(Value::Null);
+        topic
+            .entry("env")
+            .or_insert_with(|| Value::Object(Map::new()));
+        topic.entry("shell").or_insert(Value::Null);
+    }
+}
+
+/// Topics may be marked as dangerous.
+fn migrate_v6_to_v7(root: &mut Map<String, Value>) {
+    for topic in entries_mut(root, "topics") {
+        topic.entry("dangerous").or_insert(Value::Bool(false));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn unversioned_library_gets_the_defaults() {
+        let mut document = json!({
+            "topics": [{ "title": "list", "command": "ls" }]
+        });
+        assert_eq!(migrate(&mut document).unwrap(), Some(0));
+        assert_eq!(document["version"], CURRENT_VERSION);
+        assert_eq!(document["links"], json!([]));
+        let topic = &document["topics"][0];
+        assert_eq!(topic["description"], "");
+        assert_eq!(topic["category"], "default");
+        assert!(topic["id"].is_string());
+        assert!(topic["created_at"].is_string());
+        assert_eq!(topic["last_used_at"], Value::Null);
+        assert_eq!(topic["use_count"], 0);
+    }
+
+    #[test]
+    fn missing_ids_do_not_clash_with_existing_ones() {
+        let mut document = json!({
+            "version": 1,
+            "topics": [
+                { "id": "abc", "title": "a", "command": "a", "category": "x", "description": "" },
+                { "title": "b", "command": "b", "category": "x", "description": "" },
+                { "title": "c", "command": "c", "category": "x", "description": "" }
+            ],
+            "links": []
+        });
+        assert_eq!(migrate(&mut document).unwrap(), Some(1));
+        let ids: Vec<&str> = document["topics"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|topic| topic["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids[0], "abc");
+        assert!(ids[1] != ids[0] && ids[2] != ids[0] && ids[1] != ids[2]);
+    }
+
+    #[test]
+    fn existing_fields_are_kept() {
+        let mut document = json!({
+            "topics": [{ "title": "a", "command": "a", "category": "x", "description": "d" }],
+            "links": [{ "title": "docs", "url": "https://example.com" }]
+        });
+        migrate(&mut document).unwrap();
+        assert_eq!(document["topics"][0]["category"], "x");
+        assert_eq!(document["topics"][0]["description"], "d");
+        assert_eq!(document["links"][0]["title"], "docs");
+    }
+
+    #[test]
+    fn current_library_is_left_alone() {
+        let mut document = json!({ "version": CURRENT_VERSION, "topics": [], "links": [] });
+        let original = document.clone();
+        assert_eq!(migrate(&mut document).unwrap(), None);
+        assert_eq!(document, original);
+    }
+
+    #[test]
+    fn newer_or_invalid_versions_are_refused() {
+        let mut newer = json!({ "version": CURRENT_VERSION + 1 });
+        assert!(matches!(
+            migrate(&mut newer),
+            Err(TermnoteError::UnsupportedVersion(version)) if version == CURRENT_VERSION + 1
+        ));
+        let mut invalid = json!({ "version": "one" });
+        assert!(matches!(
+            migrate(&mut invalid),
+            Err(TermnoteError::Parse(_))
+        ));
+        let mut not_an_object = json!([]);
+        assert!(matches!(
+            migrate(&mut not_an_object),
+            Err(TermnoteError::Parse(_))
+        ));
+    }
+}
@@ -1,11 +1,60 @@
+mod backup;
+mod config;
 mod database_repository;
 mod display;
+mod encryption;
+mod execution_log;
+mod file_utils;
+mod git_sync;
+mod journal;
+mod migration;
+mod profile;
+mod recovery;
+mod repository;
+mod safety;
+#[cfg(feature = "sqlite")]
+mod sqlite_repository;
 
+pub use backup::Backup;
+pub use backup::BackupStore;
+pub use config::Backend;
+pub use config::Config;
+pub use config::Settings;
+pub use config::LOCAL_FILE;
+pub use database_repository::generate_id;
+pub use database_repository::normalize_tags;
 pub use database_repository::Library;
 pub use database_repository::Link;
 pub use database_repository::ObjectDB;
+pub use database_repository::Origin;
+pub use database_repository::Shell;
+pub use database_repository::Step;
 pub use database_repository::Topic;
+pub use display::display_error;
 pub use display::display_text;
+pub use display::display_warning;
+pub use display::prompt_confirmation;
+pub use display::prompt_input;
 pub use display::MenuEvent;
 pub use display::TerminalUI;
 pub use display::TerminalUrlUI;
+pub use encryption::is_encrypted;
+pub use encryption::Cipher;
+pub use encryption::KeySource;
+pub use execution_log::Execution;
+pub use execution_log::ExecutionLog;
+pub use file_utils::sibling_path;
+pub use file_utils::FileLock;
+pub use git_sync::GitSync;
+pub use journal::Change;
+pub use journal::Journal;
+pub use journal::JournalEntry;
+pub use migration::CURRENT_VERSION;
+pub use profile::ProfileStore;
+pub use recovery::Recovery;
+pub use repository::MemoryDB;
+pub use repository::Repository;
+pub use safety::danger_reasons;
+pub use safety::risky_patterns;
+#[cfg(feature = "sqlite")]
+pub use sqlite_repository::SqliteDB;
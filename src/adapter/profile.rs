@@ -0,0 +1,197 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::adapter::encryption::encryption_marker;
+use crate::adapter::file_utils::{sibling_path, write_atomic};
+use crate::TermnoteError;
+
+/// Name under which the library in the termnote home directory is listed.
+pub const DEFAULT_PROFILE: &str = "default";
+
+pub const PROFILES_FOLDER: &str = "profiles";
+const ACTIVE_FILE: &str = "active_profile";
+const EXTENSIONS: [&str; 2] = ["json", "sqlite"];
+
+/// Named libraries kept in the `profiles` folder of the termnote home
+/// directory, plus the profile selected by `tn profile use`.
+pub struct ProfileStore {
+    home: PathBuf,
+}
+
+impl ProfileStore {
+    pub fn new(home: &Path) -> Self {
+        Self {
+            home: home.to_path_buf(),
+        }
+    }
+
+    pub fn dir(&self) -> PathBuf {
+        self.home.join(PROFILES_FOLDER)
+    }
+
+    /// Library file of the profile with the given extension.
+    pub fn path(&self, name: &str, extension: &str) -> PathBuf {
+        self.dir().join(format!("{}.{}", name, extension))
+    }
+
+    /// Existing library file of the profile, whatever its backend.
+    pub fn existing_path(&self, name: &str) -> Option<PathBuf> {
+        EXTENSIONS
+            .iter()
+            .map(|extension| self.path(name, extension))
+            .find(|path| path.is_file())
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        name == DEFAULT_PROFILE || self.existing_path(name).is_some()
+    }
+
+    /// Names of all profiles, the default one first.
+    pub fn list(&self) -> Result<Vec<String>, TermnoteError> {
+        let mut names = Vec::new();
+        if self.dir().is_dir() {
+            for entry in fs::read_dir(self.dir())? {
+                let path = entry?.path();
+                let is_library = path
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| EXTENSIONS.contains(&extension));
+                if let (true, Some(stem)) = (is_library, path.file_stem()) {
+                    let name = stem.to_string_lossy().to_string();
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+        names.sort();
+        names.insert(0, DEFAULT_PROFILE.to_string());
+        Ok(names)
+    }
+
+    /// Profile selected by `tn profile use`, `None` for the default one.
+    pub fn active(&self) -> Result<Option<String>, TermnoteError> {
+        let path = self.home.join(ACTIVE_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let name = fs::read_to_string(path)?.trim().to_string();
+        if name.is_empty() || name == DEFAULT_PROFILE {
+            return Ok(None);
+        }
+        Ok(Some(name))
+    }
+
+    pub fn set_active(&self, name: &str) -> Result<(), TermnoteError> {
+        if !self.exists(name) {
+            return Err(unknown_profile(name));
+        }
+        fs::create_dir_all(&self.home)?;
+        write_atomic(&self.home.join(ACTIVE_FILE), name.as_bytes())?;
+        Ok(())
+    }
+
+    /// Removes the library file of the profile along with its lock file.
+    pub fn delete(&self, name: &str) -> Result<(), TermnoteError> {
+        if name == DEFAULT_PROFILE {
+            return Err(TermnoteError::Validation(
+                "The default profile cannot be deleted".to_string(),
+            ));
+        }
+        if self.active()?.as_deref() == Some(name) {
+            return Err(TermnoteError::Validation(format!(
+                "Profile {} is in use, switch to another profile first",
+                name
+            )));
+        }
+        let path = self
+            .existing_path(name)
+            .ok_or_else(|| unknown_profile(name))?;
+        fs::remove_file(&path)?;
+        for sibling in [sibling_path(&path, ".lock"), encryption_marker(&path)] {
+            if sibling.exists() {
+                fs::remove_file(sibling)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `name` can be used as a file name and does not clash with
+    /// the files of the default library.
+    pub fn validate_name(name: &str) -> Result<(), TermnoteError> {
+        let valid_chars = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if name.is_empty() || !valid_chars {
+            return Err(TermnoteError::Validation(format!(
+                "Invalid profile name '{}', use letters, digits, '-' and '_'",
+                name
+            )));
+        }
+        if name == DEFAULT_PROFILE || name == "db" {
+            return Err(TermnoteError::Validation(format!(
+                "The profile name '{}' is reserved",
+                name
+            )));
+        }
+        Ok(())
+    }
+}
+
+pub fn unknown_profile(name: &str) -> TermnoteError {
+    TermnoteError::NotFound(format!(
+        "No profile named {}, create it with `tn profile create {}`",
+        name, name
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_are_listed_selected_and_deleted() {
+        let home = tempfile::tempdir().unwrap();
+        let profiles = ProfileStore::new(home.path());
+        assert_eq!(profiles.list().unwrap(), [DEFAULT_PROFILE]);
+        fs::create_dir_all(profiles.dir()).unwrap();
+        for (name, extension) in [("work", "json"), ("home", "sqlite"), ("work", "sqlite")] {
+            fs::write(profiles.path(name, extension), "{}").unwrap();
+        }
+        fs::write(profiles.dir().join("notes.txt"), "").unwrap();
+        assert_eq!(profiles.list().unwrap(), [DEFAULT_PROFILE, "home", "work"]);
+
+        assert!(matches!(
+            profiles.set_active("missing"),
+            Err(TermnoteError::NotFound(_))
+        ));
+        profiles.set_active("home").unwrap();
+        assert_eq!(profiles.active().unwrap().as_deref(), Some("home"));
+        assert!(matches!(
+            profiles.delete("home"),
+            Err(TermnoteError::Validation(_))
+        ));
+        profiles.set_active(DEFAULT_PROFILE).unwrap();
+        assert!(profiles.active().unwrap().is_none());
+        let marker = encryption_marker(&profiles.path("home", "sqlite"));
+        fs::write(&marker, "").unwrap();
+        assert_eq!(profiles.list().unwrap(), [DEFAULT_PROFILE, "home", "work"]);
+        profiles.delete("home").unwrap();
+        assert!(!profiles.exists("home"));
+        assert!(!marker.exists());
+        assert!(matches!(
+            profiles.delete(DEFAULT_PROFILE),
+            Err(TermnoteError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn names_must_be_plain_and_not_reserved() {
+        for name in ["work", "team-ops", "a_1"] {
+            assert!(ProfileStore::validate_name(name).is_ok(), "{}", name);
+        }
+        for name in ["", "../work", "my work", "default", "db"] {
+            assert!(ProfileStore::validate_name(name).is_err(), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde_json::{Deserializer, Map, Value};
+
+use crate::adapter::migration;
+use crate::adapter::{Library, Link, Topic};
+
+/// Outcome of recovering a library which could not be parsed.
+pub struct Recovery {
+    /// Where the damaged file was moved to.
+    pub damaged_path: PathBuf,
+    pub topics: usize,
+    pub links: usize,
+    /// Entries which were found in the damaged file but could not be read.
+    pub lost_topics: usize,
+    pub lost_links: usize,
+}
+
+/// Entries salvaged from a damaged library.
+pub struct Salvage {
+    pub library: Library,
+    pub lost_topics: usize,
+    pub lost_links: usize,
+}
+
+/// Salvages every topic and link which can be read on its own. `document` is
+/// the parsed file if only its structure is wrong, otherwise the raw contents
+/// are scanned for JSON objects which look like entries.
+pub fn salvage(document: Option<Value>, contents: &[u8]) -> Salvage {
+    let (topics, links, expected_topics, expected_links) = match document {
+        Some(document) => {
+            let topics = document_entries(&document, "topics");
+            let links = document_entries(&document, "links");
+            let (topic_count, link_count) = (topics.len(), links.len());
+            (topics, links, topic_count, link_count)
+        }
+        None => {
+            let text = String::from_utf8_lossy(contents);
+            let (topics, links): (Vec<_>, Vec<_>) = scan_objects(&text)
+                .into_iter()
+                .partition(|object| object.contains_key("command"));
+            // entries too damaged to be found as objects still left their keys
+            let (topic_starts, link_starts) = count_entries(&text);
+            let expected_topics = topic_starts.max(topics.len());
+            let expected_links = link_starts.max(links.len());
+            (topics, links, expected_topics, expected_links)
+        }
+    };
+    let library = Library {
+        topics: topics
+            .into_iter()
+            .filter_map(|topic| read_entry::<Topic>(topic, "topics"))
+            .collect(),
+        links: links
+            .into_iter()
+            .filter_map(|link| read_entry::<Link>(link, "links"))
+            .collect(),
+        ..Library::default()
+    };
+    Salvage {
+        lost_topics: expected_topics.saturating_sub(library.topics.len()),
+        lost_links: expected_links.saturating_sub(library.links.len()),
+        library,
+    }
+}
+
+fn document_entries(document: &Value, key: &str) -> Vec<Map<String, Value>> {
+    document
+        .get(key)
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.as_object().cloned())
+        .collect()
+}
+
+/// Finds the JSON objects with a `title` and a `command` or `url` key which
+/// still parse, skipping the steps of topics.
+fn scan_objects(text: &str) -> Vec<Map<String, Value>> {
+    let mut objects = Vec::new();
+    let mut position = 0;
+    while let Some(offset) = text[position..].find('{') {
+        let start = position + offset;
+        let mut stream = Deserializer::from_str(&text[start..]).into_iter::<Map<String, Value>>();
+        match stream.next() {
+            Some(Ok(object))
+                if object.contains_key("title")
+                    && (object.contains_key("command") || object.contains_key("url")) =>
+            {
+                objects.push(object);
+                position = start + stream.byte_offset();
+            }
+            _ => position = start + 1,
+        }
+    }
+    objects
+}
+
+/// Counts the `title` keys of topics and links, each of which starts an
+/// entry, attributing them to the `topics` or `links` key seen last. Steps
+/// have no title, so unlike `command` it is not found within topics.
+fn count_entries(text: &str) -> (usize, usize) {
+    let mut keys: Vec<(usize, &str)> = ["topics", "links", "title"]
+        .into_iter()
+        .flat_map(|key| {
+            key_positions(text, key)
+                .into_iter()
+                .map(move |index| (index, key))
+        })
+        .collect();
+    keys.sort_unstable();
+    let (mut topics, mut links) = (0, 0);
+    let mut section = "";
+    for (_, key) in keys {
+        match (key, section) {
+            ("title", "topics") => topics += 1,
+            ("title", "links") => links += 1,
+            ("title", _) => {}
+            _ => section = key,
+        }
+    }
+    (topics, links)
+}
+
+/// Positions of `"key":` outside of escaped strings.
+fn key_positions(text: &str, key: &str) -> Vec<usize> {
+    let quoted = format!("\"{}\"", key);
+    text.match_indices(&quoted)
+        .map(|(index, _)| index)
+        .filter(|index| !text[..*index].ends_with('\\'))
+        .filter(|index| text[index + quoted.len()..].trim_start().starts_with(':'))
+        .collect()
+}
+
+/// Reads a single entry, filling in the fields the migrations know defaults for.
+fn read_entry<T: DeserializeOwned>(entry: Map<String, Value>, key: &str) -> Option<T> {
+    let mut document = Value::Object(Map::from_iter([
+        ("version".to_string(), Value::from(0)),
+        (key.to_string(), Value::Array(vec![Value::Object(entry)])),
+    ]));
+    migration::migrate(&mut document).ok()?;
+    let entries = document.get_mut(key)?.as_array_mut()?;
+    serde_json::from_value(entries.pop()?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn salvages_the_readable_entries_of_a_document() {
+        let document = json!({
+            "version": 7,
+            "topics": [
+                { "title": "list", "command": "ls", "category": "files" },
+                { "title": "broken", "command": 42 }
+            ],
+            "links": [{ "title": "docs", "url": "https://example.com" }]
+        });
+        let salvage = salvage(Some(document), b"");
+        assert_eq!(salvage.library.topics.len(), 1);
+        assert_eq!(salvage.library.topics[0].title, "list");
+        assert_eq!(salvage.library.links.len(), 1);
+        assert_eq!((salvage.lost_topics, salvage.lost_links), (1, 0));
+    }
+
+    #[test]
+    fn scans_damaged_contents_for_entries() {
+        let contents = br#"{"version": 7, "topics": [
+            {"title": "list", "command": "ls", "category": "files"},
+            {"title": "deploy", "category": "ops",
+             "steps": [{"description": "build", "command": "make"}], "command": "make
+            {"title": "quote", "command": "echo \"command\": x", "category": "misc"}
+        ], "links": [
+            {"title": "docs", "url": "https://example.com"},
+            {"title": "broken", "url": https://example.org}
+        ]}"#;
+        let salvage = salvage(None, contents);
+        let titles: Vec<&str> = salvage
+            .library
+            .topics
+            .iter()
+            .map(|topic| topic.title.as_str())
+            .collect();
+        assert_eq!(titles, ["list", "quote"]);
+        assert_eq!(salvage.library.links.len(), 1);
+        assert_eq!((salvage.lost_topics, salvage.lost_links), (1, 1));
+    }
+
+    #[test]
+    fn counts_entries_by_their_titles() {
+        let text = r#"{"topics": [{"title": "a", "steps": [{"command": "x"}]},
+            {"title" : "b"}], "links": [{"title": "c", "url": "u"}]}"#;
+        assert_eq!(count_entries(text), (2, 1));
+        assert_eq!(count_entries(r#"{"title": "no section"}"#), (0, 0));
+    }
+}
@@ -0,0 +1,216 @@
+use std::path::PathBuf;
+
+use crate::adapter::{Cipher, Library, Link, Topic};
+use crate::TermnoteError;
+
+/// Storage backend for the termnote library.
+///
+/// Implementors only have to provide access to the in-memory `Library` plus
+/// `load`/`save`; the per-entry operations default to mutating the library and
+/// persisting it as a whole. Backends which can persist single entries more
+/// efficiently override them.
+pub trait Repository {
+    fn load(&mut self) -> Result<(), TermnoteError>;
+
+    fn save(&self) -> Result<(), TermnoteError>;
+
+    fn library(&self) -> &Library;
+
+    fn library_mut(&mut self) -> &mut Library;
+
+    /// Picks up changes other processes made since the library was loaded, before it
+    /// gets modified. Backends which can tell that nothing changed skip the load.
+    fn reload(&mut self) -> Result<(), TermnoteError> {
+        self.load()
+    }
+
+    /// Lock file guarding load-modify-save cycles across processes, if any.
+    fn lock_path(&self) -> Option<PathBuf> {
+        None
+    }
+
+    /// Stores the library encrypted with `cipher` from now on, or in plaintext
+    /// for `None`.
+    fn set_cipher(&mut self, _cipher: Option<Cipher>) -> Result<(), TermnoteError> {
+        Err(TermnoteError::Config(
+            "This storage backend does not support encryption".to_string(),
+        ))
+    }
+
+    /// Replaces the whole library, e.g. when restoring a backup.
+    fn replace_library(&mut self, library: Library) -> Result<(), TermnoteError> {
+        *self.library_mut() = library;
+        self.save()
+    }
+
+    fn insert_topic(&mut self, topic: Topic) -> Result<(), TermnoteError> {
+        self.library_mut().topics.push(topic);
+        self.save()
+    }
+
+    fn update_topic(&mut self, id: &str, topic: Topic) -> Result<(), TermnoteError> {
+        match self
+            .library_mut()
+            .topics
+            .iter_mut()
+            .find(|tpc| tpc.id == id)
+        {
+            Some(entry) => *entry = topic,
+            None => return Err(TermnoteError::NotFound(format!("No topic with id {}", id))),
+        }
+        self.save()
+    }
+
+    fn delete_topic(&mut self, id: &str) -> Result<Topic, TermnoteError> {
+        let index = self
+            .library()
+            .topics
+            .iter()
+            .position(|tpc| tpc.id == id)
+            .ok_or_else(|| TermnoteError::NotFound(format!("No topic with id {}", id)))?;
+        let topic = self.library_mut().topics.remove(index);
+        self.save()?;
+        Ok(topic)
+    }
+
+    fn insert_link(&mut self, link: Link) -> Result<(), TermnoteError> {
+        self.library_mut().links.push(link);
+        self.save()
+    }
+
+    fn update_link(&mut self, id: &str, link: Link) -> Result<(), TermnoteError> {
+        match self.library_mut().links.iter_mut().find(|lnk| lnk.id == id) {
+            Some(entry) => *entry = link,
+            None => return Err(TermnoteError::NotFound(format!("No link with id {}", id))),
+        }
+        self.save()
+    }
+
+    fn delete_link(&mut self, id: &str) -> Result<Link, TermnoteError> {
+        let index = self
+            .library()
+            .links
+            .iter()
+            .position(|lnk| lnk.id == id)
+            .ok_or_else(|| TermnoteError::NotFound(format!("No link with id {}", id)))?;
+        let link = self.library_mut().links.remove(index);
+        self.save()?;
+        Ok(link)
+    }
+
+    fn search_topics(&self, query: &str) -> Result<Vec<Topic>, TermnoteError> {
+        let query = query.to_lowercase();
+        Ok(self
+            .library()
+            .topics
+            .iter()
+            .filter(|topic| {
+                topic.title.to_lowercase().contains(&query)
+                    || topic.description.to_lowercase().contains(&query)
+                    || topic.category.to_lowercase().contains(&query)
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn search_links(&self, query: &str) -> Result<Vec<Link>, TermnoteError> {
+        let query = query.to_lowercase();
+        Ok(self
+            .library()
+            .links
+            .iter()
+            .filter(|link| link.title.to_lowercase().contains(&query))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Repository which keeps the library in memory only, e.g. for tests.
+#[derive(Default)]
+pub struct MemoryDB {
+    pub library: Library,
+}
+
+impl MemoryDB {
+    pub fn new(library: Library) -> Self {
+        Self { library }
+    }
+}
+
+impl Repository for MemoryDB {
+    fn load(&mut self) -> Result<(), TermnoteError> {
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), TermnoteError> {
+        Ok(())
+    }
+
+    fn library(&self) -> &Library {
+        &self.library
+    }
+
+    fn library_mut(&mut self) -> &mut Library {
+        &mut self.library
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topic(title: &str) -> Topic {
+        Topic {
+            id: title.to_string(),
+            title: title.to_string(),
+            description: String::new(),
+            command: "ls".to_string(),
+            category: "files".to_string(),
+            tags: Vec::new(),
+            steps: Vec::new(),
+            cwd: None,
+            env: Default::default(),
+            shell: None,
+            dangerous: false,
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            last_used_at: None,
+            use_count: 0,
+            origin: Default::default(),
+        }
+    }
+
+    #[test]
+    fn default_operations_change_the_library() {
+        let mut db = MemoryDB::default();
+        db.insert_topic(topic("a")).unwrap();
+        db.insert_topic(topic("b")).unwrap();
+        db.update_topic("b", topic("c")).unwrap();
+        assert_eq!(db.delete_topic("a").unwrap().title, "a");
+        let titles: Vec<&str> = db
+            .library()
+            .topics
+            .iter()
+            .map(|t| t.title.as_str())
+            .collect();
+        assert_eq!(titles, ["c"]);
+    }
+
+    #[test]
+    fn operations_on_missing_entries_fail() {
+        let mut db = MemoryDB::default();
+        assert!(matches!(
+            db.update_topic("a", topic("a")),
+            Err(TermnoteError::NotFound(_))
+        ));
+        assert!(matches!(
+            db.delete_topic("a"),
+            Err(TermnoteError::NotFound(_))
+        ));
+        assert!(matches!(
+            db.delete_link("a"),
+            Err(TermnoteError::NotFound(_))
+        ));
+        assert!(db.library().topics.is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+/// Reasons to ask for a typed confirmation before running `command`: the
+/// topic being marked as dangerous and the risky operations found in it.
+pub fn danger_reasons(dangerous: bool, command: &str) -> Vec<String> {
+    let mut reasons = Vec::new();
+    if dangerous {
+        reasons.push("marked as dangerous".to_string());
+    }
+    reasons.extend(risky_patterns(command).into_iter().map(str::to_string));
+    reasons
+}
+
+/// Risky operations in a shell command, e.g. `rm -rf` or `kubectl delete`.
+/// This is a heuristic on the words of each simple command, it does not
+/// understand quoting or variables.
+pub fn risky_patterns(command: &str) -> Vec<&'static str> {
+    let mut risks = Vec::new();
+    let mut add = |risk: &'static str| {
+        if !risks.contains(&risk) {
+            risks.push(risk);
+        }
+    };
+    for segment in command.split(['\n', ';', '|', '&']) {
+        let words: Vec<&str> = segment.split_whitespace().collect();
+        if let Some(risk) = risky_command(program_words(&words)) {
+            add(risk);
+        }
+        let redirect = segment.replace(' ', "");
+        if redirect.contains(">/dev/sd") || redirect.contains(">/dev/nvme") {
+            add("overwrites a disk device");
+        }
+    }
+    let lowered = command.to_lowercase();
+    if ["drop table", "drop database", "truncate table"]
+        .iter()
+        .any(|statement| lowered.contains(statement))
+    {
+        add("drops or truncates database tables");
+    }
+    if command.replace(' ', "").contains(":(){:|:&};:") {
+        add("fork bomb");
+    }
+    risks
+}
+
+/// The words of a simple command starting at the program, skipping `sudo`
+/// and variable assignments in front of it.
+fn program_words<'a, 'b>(words: &'a [&'b str]) -> &'a [&'b str] {
+    let start = words
+        .iter()
+        .position(|word| !matches!(*word, "sudo" | "doas" | "env" | "exec") && !word.contains('='))
+        .unwrap_or(words.len());
+    &words[start..]
+}
+
+fn risky_command(words: &[&str]) -> Option<&'static str> {
+    let (program, args) = words.split_first()?;
+    let program = program.rsplit('/').next().unwrap_or(program);
+    let has = |flag: &str| args.contains(&flag);
+    // short options may be combined, as in `rm -rf`
+    let has_short = |flag: char| {
+        args.iter()
+            .any(|arg| arg.starts_with('-') && !arg.starts_with("--") && arg.contains(flag))
+    };
+    let subcommand = args.first().copied().unwrap_or_default();
+    match program {
+        "rm" if (has_short('r') || has_short('R') || has("--recursive"))
+            && (has_short('f') || has("--force")) =>
+        {
+            Some("removes files recursively without asking")
+        }
+        "dd" if args.iter().any(|arg| arg.starts_with("of=")) => Some("writes raw data with dd"),
+        "shred" | "wipefs" | "fdisk" | "parted" => Some("wipes or repartitions disks"),
+        _ if program.starts_with("mkfs") => Some("formats a file system"),
+        "shutdown" | "reboot" | "halt" | "poweroff" => Some("shuts down or reboots the machine"),
+        "chmod" | "chown" if has_short('R') || has("--recursive") => {
+            Some("changes permissions recursively")
+        }
+        "git" if subcommand == "push" && (has_short('f') || has("--force")) => {
+            Some("force pushes with git")
+        }
+        "git" if subcommand == "reset" && has("--hard") => Some("discards changes with git reset"),
+        "git" if subcommand == "clean" && has_short('f') => Some("deletes untracked files"),
+        "kubectl" if matches!(subcommand, "delete" | "drain") => {
+            Some("deletes Kubernetes resources")
+        }
+        "helm" if matches!(subcommand, "uninstall" | "delete") => Some("uninstalls a Helm release"),
+        "terraform" if subcommand == "destroy" => Some("destroys Terraform infrastructure"),
+        "docker" if args.starts_with(&["system", "prune"]) => Some("prunes Docker data"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_recursive_forced_removal() {
+        let reasons = ["removes files recursively without asking"];
+        assert_eq!(risky_patterns("rm -rf build"), reasons);
+        assert_eq!(risky_patterns("rm -r -f build"), reasons);
+        assert_eq!(
+            risky_patterns("sudo /bin/rm --recursive --force /"),
+            reasons
+        );
+        assert_eq!(risky_patterns("FOO=1 rm -Rf build"), reasons);
+        assert!(risky_patterns("rm -r build").is_empty());
+        assert!(risky_patterns("rm -f build.log").is_empty());
+    }
+
+    #[test]
+    fn checks_every_simple_command() {
+        assert_eq!(
+            risky_patterns("cd /tmp && rm -rf x; git push --force | cat"),
+            [
+                "removes files recursively without asking",
+                "force pushes with git"
+            ]
+        );
+        assert_eq!(
+            risky_patterns("rm -rf a && rm -rf b"),
+            ["removes files recursively without asking"]
+        );
+    }
+
+    #[test]
+    fn finds_other_risky_operations() {
+        assert_eq!(
+            risky_patterns("dd if=image.iso of=/dev/sdb"),
+            ["writes raw data with dd"]
+        );
+        assert_eq!(
+            risky_patterns("cat image > /dev/sda"),
+            ["overwrites a disk device"]
+        );
+        assert_eq!(
+            risky_patterns("kubectl delete pod web"),
+            ["deletes Kubernetes resources"]
+        );
+        assert_eq!(
+            risky_patterns("psql -c 'DROP TABLE users'"),
+            ["drops or truncates database tables"]
+        );
+        assert_eq!(risky_patterns(":(){ :|:& };:"), ["fork bomb"]);
+    }
+
+    #[test]
+    fn ignores_harmless_commands() {
+        for command in [
+            "ls -la",
+            "git push",
+            "git reset HEAD~1",
+            "kubectl get pods",
+            "echo rm -rf",
+        ] {
+            assert!(risky_patterns(command).is_empty(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn danger_reasons_include_the_mark() {
+        assert_eq!(danger_reasons(true, "ls"), ["marked as dangerous"]);
+        assert!(danger_reasons(false, "ls").is_empty());
+    }
+}
@@ -0,0 +1,670 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, Connection, Row};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::adapter::file_utils::sibling_path;
+use crate::adapter::migration::CURRENT_VERSION;
+use crate::adapter::{Library, Link, ObjectDB, Origin, Repository, Shell, Topic};
+use crate::TermnoteError;
+
+/// Version of the database schema, tracked in `PRAGMA user_version`. It follows the JSON
+/// library version up to `CURRENT_VERSION`, later steps only concern the database.
+const SCHEMA_VERSION: u32 = CURRENT_VERSION + 1;
+
+/// Schema steps, the step at index `n` migrates the database from version `n`
+/// to `n + 1`.
+const SCHEMA_MIGRATIONS: [&str; SCHEMA_VERSION as usize] = [
+    "
+    CREATE TABLE IF NOT EXISTS topics (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        title TEXT NOT NULL,
+        description TEXT NOT NULL,
+        command TEXT NOT NULL,
+        category TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_topics_title ON topics (title COLLATE NOCASE);
+    CREATE INDEX IF NOT EXISTS idx_topics_category ON topics (category COLLATE NOCASE);
+    CREATE TABLE IF NOT EXISTS links (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        title TEXT NOT NULL,
+        url TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_links_title ON links (title COLLATE NOCASE);
+",
+    "
+    ALTER TABLE topics ADD COLUMN uid TEXT;
+    UPDATE topics SET uid = lower(hex(randomblob(4))) WHERE uid IS NULL;
+    CREATE UNIQUE INDEX IF NOT EXISTS idx_topics_uid ON topics (uid);
+    ALTER TABLE links ADD COLUMN uid TEXT;
+    UPDATE links SET uid = lower(hex(randomblob(4))) WHERE uid IS NULL;
+    CREATE UNIQUE INDEX IF NOT EXISTS idx_links_uid ON links (uid);
+",
+    "
+    ALTER TABLE topics ADD COLUMN created_at TEXT;
+    ALTER TABLE topics ADD COLUMN updated_at TEXT;
+    ALTER TABLE topics ADD COLUMN last_used_at TEXT;
+    ALTER TABLE topics ADD COLUMN use_count INTEGER NOT NULL DEFAULT 0;
+    UPDATE topics SET created_at = strftime('%Y-%m-%d %H:%M:%f+00:00', 'now'),
+                      updated_at = strftime('%Y-%m-%d %H:%M:%f+00:00', 'now');
+    ALTER TABLE links ADD COLUMN created_at TEXT;
+    ALTER TABLE links ADD COLUMN updated_at TEXT;
+    ALTER TABLE links ADD COLUMN last_used_at TEXT;
+    ALTER TABLE links ADD COLUMN use_count INTEGER NOT NULL DEFAULT 0;
+    UPDATE links SET created_at = strftime('%Y-%m-%d %H:%M:%f+00:00', 'now'),
+                     updated_at = strftime('%Y-%m-%d %H:%M:%f+00:00', 'now');
+",
+    "
+    ALTER TABLE topics ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
+    ALTER TABLE links ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
+",
+    "
+    ALTER TABLE topics ADD COLUMN steps TEXT NOT NULL DEFAULT '[]';
+",
+    "
+    ALTER TABLE topics ADD COLUMN cwd TEXT;
+    ALTER TABLE topics ADD COLUMN env TEXT NOT NULL DEFAULT '{}';
+    ALTER TABLE topics ADD COLUMN shell TEXT;
+",
+    "
+    ALTER TABLE topics ADD COLUMN dangerous INTEGER NOT NULL DEFAULT 0;
+",
+    "
+    CREATE VIRTUAL TABLE topics_search USING fts5(
+        title, description, category, content = 'topics', content_rowid = 'id'
+    );
+    CREATE TRIGGER topics_search_insert AFTER INSERT ON topics BEGIN
+        INSERT INTO topics_search (rowid, title, description, category)
+        VALUES (new.id, new.title, new.description, new.category);
+    END;
+    CREATE TRIGGER topics_search_delete AFTER DELETE ON topics BEGIN
+        INSERT INTO topics_search (topics_search, rowid, title, description, category)
+        VALUES ('delete', old.id, old.title, old.description, old.category);
+    END;
+    CREATE TRIGGER topics_search_update AFTER UPDATE ON topics BEGIN
+        INSERT INTO topics_search (topics_search, rowid, title, description, category)
+        VALUES ('delete', old.id, old.title, old.description, old.category);
+        INSERT INTO topics_search (rowid, title, description, category)
+        VALUES (new.id, new.title, new.description, new.category);
+    END;
+    INSERT INTO topics_search (topics_search) VALUES ('rebuild');
+    CREATE VIRTUAL TABLE links_search USING fts5(
+        title, content = 'links', content_rowid = 'id'
+    );
+    CREATE TRIGGER links_search_insert AFTER INSERT ON links BEGIN
+        INSERT INTO links_search (rowid, title) VALUES (new.id, new.title);
+    END;
+    CREATE TRIGGER links_search_delete AFTER DELETE ON links BEGIN
+        INSERT INTO links_search (links_search, rowid, title) VALUES ('delete', old.id, old.title);
+    END;
+    CREATE TRIGGER links_search_update AFTER UPDATE ON links BEGIN
+        INSERT INTO links_search (links_search, rowid, title) VALUES ('delete', old.id, old.title);
+        INSERT INTO links_search (rowid, title) VALUES (new.id, new.title);
+    END;
+    INSERT INTO links_search (links_search) VALUES ('rebuild');
+",
+];
+
+/// The row id only keeps the insertion order, entries are addressed by `uid`.
+/// Tags and steps are stored as JSON arrays, the environment as a JSON object.
+const TOPIC_COLUMNS: &str = "uid, title, description, command, category, \
+    created_at, updated_at, last_used_at, use_count, tags, steps, cwd, env, shell, dangerous";
+const LINK_COLUMNS: &str = "uid, title, url, created_at, updated_at, last_used_at, use_count, tags";
+
+pub struct SqliteDB {
+    connection: Connection,
+    library: Library,
+    path: PathBuf,
+    /// `PRAGMA data_version` when the library was loaded, it changes once another
+    /// connection commits to the database.
+    data_version: i64,
+}
+
+impl SqliteDB {
+    /// Opens the database at `path`, importing the JSON library at
+    /// `json_path` when the database is created.
+    pub fn init(path: &Path, json_path: &Path) -> Result<Self, TermnoteError> {
+        if let Some(config_path) = path.parent() {
+            if !config_path.as_os_str().is_empty() && !config_path.exists() {
+                fs::create_dir_all(config_path)?;
+            }
+        }
+        // carry over an existing JSON library when switching backends, read
+        // before the database is created so that a failure can be retried
+        let imported = if !path.exists() && json_path.exists() {
+            Some(ObjectDB::read_library(json_path)?)
+        } else {
+            None
+        };
+        let mut db = Self::open(path)?;
+        if let Some(library) = imported {
+            db.library = library;
+            db.save()?;
+        }
+        Ok(db)
+    }
+
+    pub fn open(path: &Path) -> Result<Self, TermnoteError> {
+        let connection = Connection::open(path)?;
+        Self::migrate(&connection)?;
+        let mut db = Self {
+            connection,
+            library: Library::default(),
+            path: path.to_path_buf(),
+            data_version: 0,
+        };
+        db.load()?;
+        Ok(db)
+    }
+
+    fn migrate(connection: &Connection) -> Result<(), TermnoteError> {
+        let version: u32 = connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        if version > SCHEMA_VERSION {
+            return Err(TermnoteError::UnsupportedVersion(version));
+        }
+        let tx = connection.unchecked_transaction()?;
+        for step in &SCHEMA_MIGRATIONS[version as usize..] {
+            tx.execute_batch(step)?;
+        }
+        tx.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn data_version(&self) -> Result<i64, TermnoteError> {
+        Ok(self
+            .connection
+            .pragma_query_value(None, "data_version", |row| row.get(0))?)
+    }
+
+    fn topic_from_row(row: &Row) -> rusqlite::Result<Topic> {
+        Ok(Topic {
+            id: row.get("uid")?,
+            title: row.get("title")?,
+            description: row.get("description")?,
+            command: row.get("command")?,
+            category: row.get("category")?,
+            created_at: row.get("created_at")?,
+            updated_at: row.get("updated_at")?,
+            last_used_at: row.get("last_used_at")?,
+            use_count: row.get("use_count")?,
+            tags: json_from_row(row, "tags")?,
+            steps: json_from_row(row, "steps")?,
+            cwd: row.get("cwd")?,
+            env: json_from_row(row, "env")?,
+            shell: shell_from_row(row)?,
+            dangerous: row.get("dangerous")?,
+            origin: Origin::Global,
+        })
+    }
+
+    fn insert_topic_row(connection: &Connection, topic: &Topic) -> rusqlite::Result<usize> {
+        connection.execute(
+            &format!(
+                "INSERT INTO topics ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                TOPIC_COLUMNS
+            ),
+            params![
+                topic.id,
+                topic.title,
+                topic.description,
+                topic.command,
+                topic.category,
+                topic.created_at,
+                topic.updated_at,
+                topic.last_used_at,
+                topic.use_count,
+                json_to_sql(&topic.tags),
+                json_to_sql(&topic.steps),
+                topic.cwd,
+                json_to_sql(&topic.env),
+                topic.shell.map(|shell| shell.name()),
+                topic.dangerous
+            ],
+        )
+    }
+
+    fn insert_link_row(connection: &Connection, link: &Link) -> rusqlite::Result<usize> {
+        connection.execute(
+            &format!(
+                "INSERT INTO links ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                LINK_COLUMNS
+            ),
+            params![
+                link.id,
+                link.title,
+                link.url,
+                link.created_at,
+                link.updated_at,
+                link.last_used_at,
+                link.use_count,
+                json_to_sql(&link.tags)
+            ],
+        )
+    }
+
+    fn link_from_row(row: &Row) -> rusqlite::Result<Link> {
+        Ok(Link {
+            id: row.get("uid")?,
+            title: row.get("title")?,
+            url: row.get("url")?,
+            created_at: row.get("created_at")?,
+            updated_at: row.get("updated_at")?,
+            last_used_at: row.get("last_used_at")?,
+            use_count: row.get("use_count")?,
+            tags: json_from_row(row, "tags")?,
+            origin: Origin::Global,
+        })
+    }
+}
+
+/// Plain collections of strings always serialize.
+fn json_to_sql<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+fn json_from_row<T: DeserializeOwned>(row: &Row, column: &str) -> rusqlite::Result<T> {
+    let index = row.as_ref().column_index(column)?;
+    let json: String = row.get(index)?;
+    serde_json::from_str(&json).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+fn shell_from_row(row: &Row) -> rusqlite::Result<Option<Shell>> {
+    let index = row.as_ref().column_index("shell")?;
+    let name: Option<String> = row.get(index)?;
+    name.map(|name| Shell::from_name(&name))
+        .transpose()
+        .map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(
+                index,
+                rusqlite::types::Type::Text,
+                Box::new(e),
+            )
+        })
+}
+
+/// Full-text query matching every word of a user query as a word prefix, `None` when
+/// the query holds no word to search for.
+fn match_query(query: &str) -> Option<String> {
+    let words: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+impl Repository for SqliteDB {
+    fn load(&mut self) -> Result<(), TermnoteError> {
+        let mut topic_stmt = self
+            .connection
+            .prepare(&format!("SELECT {} FROM topics ORDER BY id", TOPIC_COLUMNS))?;
+        let topics = topic_stmt
+            .query_map([], Self::topic_from_row)?
+            .collect::<rusqlite::Result<Vec<Topic>>>()?;
+        let mut link_stmt = self
+            .connection
+            .prepare(&format!("SELECT {} FROM links ORDER BY id", LINK_COLUMNS))?;
+        let links = link_stmt
+            .query_map([], Self::link_from_row)?
+            .collect::<rusqlite::Result<Vec<Link>>>()?;
+        self.library = Library {
+            version: CURRENT_VERSION,
+            topics,
+            links,
+        };
+        self.data_version = self.data_version()?;
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), TermnoteError> {
+        let tx = self.connection.unchecked_transaction()?;
+        tx.execute("DELETE FROM topics", [])?;
+        tx.execute("DELETE FROM links", [])?;
+        for topic in &self.library.topics {
+            Self::insert_topic_row(&tx, topic)?;
+        }
+        for link in &self.library.links {
+            Self::insert_link_row(&tx, link)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn library(&self) -> &Library {
+        &self.library
+    }
+
+    fn library_mut(&mut self) -> &mut Library {
+        &mut self.library
+    }
+
+    /// The own writes keep the library in step with the database, so it only has to be
+    /// loaded again after another process changed the database.
+    fn reload(&mut self) -> Result<(), TermnoteError> {
+        if self.data_version()? != self.data_version {
+            self.load()?;
+        }
+        Ok(())
+    }
+
+    fn lock_path(&self) -> Option<PathBuf> {
+        Some(sibling_path(&self.path, ".lock"))
+    }
+
+    fn insert_topic(&mut self, topic: Topic) -> Result<(), TermnoteError> {
+        Self::insert_topic_row(&self.connection, &topic)?;
+        self.library.topics.push(topic);
+        Ok(())
+    }
+
+    fn update_topic(&mut self, id: &str, topic: Topic) -> Result<(), TermnoteError> {
+        let updated = self.connection.execute(
+            "UPDATE topics SET uid = ?2, title = ?3, description = ?4, command = ?5, category = ?6,
+                               created_at = ?7, updated_at = ?8, last_used_at = ?9, use_count = ?10,
+                               tags = ?11, steps = ?12, cwd = ?13, env = ?14, shell = ?15,
+                               dangerous = ?16
+             WHERE uid = ?1",
+            params![
+                id,
+                topic.id,
+                topic.title,
+                topic.description,
+                topic.command,
+                topic.category,
+                topic.created_at,
+                topic.updated_at,
+                topic.last_used_at,
+                topic.use_count,
+                json_to_sql(&topic.tags),
+                json_to_sql(&topic.steps),
+                topic.cwd,
+                json_to_sql(&topic.env),
+                topic.shell.map(|shell| shell.name()),
+                topic.dangerous
+            ],
+        )?;
+        match self.library.topics.iter_mut().find(|tpc| tpc.id == id) {
+            Some(entry) if updated > 0 => *entry = topic,
+            _ => return Err(TermnoteError::NotFound(format!("No topic with id {}", id))),
+        }
+        Ok(())
+    }
+
+    fn delete_topic(&mut self, id: &str) -> Result<Topic, TermnoteError> {
+        let deleted = self
+            .connection
+            .execute("DELETE FROM topics WHERE uid = ?1", params![id])?;
+        match self.library.topics.iter().position(|tpc| tpc.id == id) {
+            Some(index) if deleted > 0 => Ok(self.library.topics.remove(index)),
+            _ => Err(TermnoteError::NotFound(format!("No topic with id {}", id))),
+        }
+    }
+
+    fn insert_link(&mut self, link: Link) -> Result<(), TermnoteError> {
+        Self::insert_link_row(&self.connection, &link)?;
+        self.library.links.push(link);
+        Ok(())
+    }
+
+    fn update_link(&mut self, id: &str, link: Link) -> Result<(), TermnoteError> {
+        let updated = self.connection.execute(
+            "UPDATE links SET uid = ?2, title = ?3, url = ?4,
+                              created_at = ?5, updated_at = ?6, last_used_at = ?7, use_count = ?8,
+                              tags = ?9
+             WHERE uid = ?1",
+            params![
+                id,
+                link.id,
+                link.title,
+                link.url,
+                link.created_at,
+                link.updated_at,
+                link.last_used_at,
+                link.use_count,
+                json_to_sql(&link.tags)
+            ],
+        )?;
+        match self.library.links.iter_mut().find(|lnk| lnk.id == id) {
+            Some(entry) if updated > 0 => *entry = link,
+            _ => return Err(TermnoteError::NotFound(format!("No link with id {}", id))),
+        }
+        Ok(())
+    }
+
+    fn delete_link(&mut self, id: &str) -> Result<Link, TermnoteError> {
+        let deleted = self
+            .connection
+            .execute("DELETE FROM links WHERE uid = ?1", params![id])?;
+        match self.library.links.iter().position(|lnk| lnk.id == id) {
+            Some(index) if deleted > 0 => Ok(self.library.links.remove(index)),
+            _ => Err(TermnoteError::NotFound(format!("No link with id {}", id))),
+        }
+    }
+
+    fn search_topics(&self, query: &str) -> Result<Vec<Topic>, TermnoteError> {
+        let Some(query) = match_query(query) else {
+            return Ok(Vec::new());
+        };
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT {} FROM topics
+             WHERE id IN (SELECT rowid FROM topics_search WHERE topics_search MATCH ?1)
+             ORDER BY id",
+            TOPIC_COLUMNS
+        ))?;
+        let results = stmt
+            .query_map(params![query], Self::topic_from_row)?
+            .collect::<rusqlite::Result<Vec<Topic>>>()?;
+        Ok(results)
+    }
+
+    fn search_links(&self, query: &str) -> Result<Vec<Link>, TermnoteError> {
+        let Some(query) = match_query(query) else {
+            return Ok(Vec::new());
+        };
+        let mut stmt = self.connection.prepare(&format!(
+            "SELECT {} FROM links
+             WHERE id IN (SELECT rowid FROM links_search WHERE links_search MATCH ?1)
+             ORDER BY id",
+            LINK_COLUMNS
+        ))?;
+        let results = stmt
+            .query_map(params![query], Self::link_from_row)?
+            .collect::<rusqlite::Result<Vec<Link>>>()?;
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn topic(title: &str, category: &str) -> Topic {
+        Topic {
+            id: title.to_lowercase(),
+            title: title.to_string(),
+            description: format!("{} description", title),
+            command: "ls".to_string(),
+            category: category.to_string(),
+            tags: Vec::new(),
+            steps: Vec::new(),
+            cwd: None,
+            env: Default::default(),
+            shell: None,
+            dangerous: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            last_used_at: None,
+            use_count: 0,
+            origin: Origin::Global,
+        }
+    }
+
+    fn link(title: &str, url: &str) -> Link {
+        Link {
+            id: title.to_lowercase(),
+            title: title.to_string(),
+            url: url.to_string(),
+            tags: Vec::new(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            last_used_at: None,
+            use_count: 0,
+            origin: Origin::Global,
+        }
+    }
+
+    fn titles(topics: &[Topic]) -> Vec<&str> {
+        topics.iter().map(|topic| topic.title.as_str()).collect()
+    }
+
+    #[test]
+    fn entry_changes_are_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.sqlite");
+        let mut db = SqliteDB::open(&path).unwrap();
+        db.insert_topic(topic("List", "files")).unwrap();
+        db.insert_topic(topic("Ping", "network")).unwrap();
+        db.insert_topic(topic("Disk", "files")).unwrap();
+        let mut trace = topic("Trace", "network");
+        trace.use_count = 3;
+        trace.tags = vec!["net".to_string(), "debug".to_string()];
+        trace.last_used_at = Some(Utc::now());
+        db.update_topic("ping", trace.clone()).unwrap();
+        assert_eq!(db.delete_topic("list").unwrap().title, "List");
+        db.insert_link(link("Docs", "https://example.com")).unwrap();
+        db.update_link("docs", link("Docs", "https://example.org"))
+            .unwrap();
+        assert!(matches!(
+            db.update_topic("ping", topic("x", "y")),
+            Err(TermnoteError::NotFound(_))
+        ));
+        assert!(matches!(
+            db.delete_link("missing"),
+            Err(TermnoteError::NotFound(_))
+        ));
+
+        let reopened = SqliteDB::open(&path).unwrap();
+        assert_eq!(titles(&reopened.library().topics), ["Trace", "Disk"]);
+        assert_eq!(titles(&db.library().topics), ["Trace", "Disk"]);
+        let stored = &reopened.library().topics[0];
+        assert_eq!(stored.id, "trace");
+        assert_eq!(stored.use_count, 3);
+        assert_eq!(stored.tags, ["net", "debug"]);
+        assert_eq!(
+            stored.last_used_at.map(|time| time.timestamp_millis()),
+            trace.last_used_at.map(|time| time.timestamp_millis())
+        );
+        assert_eq!(reopened.library().links[0].url, "https://example.org");
+    }
+
+    #[test]
+    fn save_replaces_the_stored_library() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.sqlite");
+        let mut db = SqliteDB::open(&path).unwrap();
+        db.insert_topic(topic("List", "files")).unwrap();
+        db.library_mut().topics = vec![topic("Ping", "network"), topic("Disk", "files")];
+        db.save().unwrap();
+        let mut reopened = SqliteDB::open(&path).unwrap();
+        reopened.load().unwrap();
+        assert_eq!(titles(&reopened.library().topics), ["Ping", "Disk"]);
+    }
+
+    #[test]
+    fn reload_picks_up_changes_of_other_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.sqlite");
+        let mut db = SqliteDB::open(&path).unwrap();
+        db.insert_topic(topic("List", "files")).unwrap();
+        db.library_mut().topics[0].title = "Unsaved".to_string();
+        db.reload().unwrap();
+        assert_eq!(titles(&db.library().topics), ["Unsaved"]);
+
+        let mut other = SqliteDB::open(&path).unwrap();
+        other.insert_topic(topic("Ping", "network")).unwrap();
+        db.reload().unwrap();
+        assert_eq!(titles(&db.library().topics), ["List", "Ping"]);
+    }
+
+    #[test]
+    fn search_matches_word_prefixes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut db = SqliteDB::open(&dir.path().join("db.sqlite")).unwrap();
+        db.insert_topic(topic("List", "files")).unwrap();
+        db.insert_topic(topic("Ping", "network")).unwrap();
+        db.insert_topic(topic("Disk 100%", "FILES")).unwrap();
+        let found = |query: &str| -> Vec<String> {
+            db.search_topics(query)
+                .unwrap()
+                .into_iter()
+                .map(|topic| topic.title)
+                .collect()
+        };
+        assert_eq!(found("files"), ["List", "Disk 100%"]);
+        assert_eq!(found("FIL"), ["List", "Disk 100%"]);
+        assert_eq!(found("ping desc"), ["Ping"]);
+        assert!(found("isk").is_empty());
+        assert!(found("%").is_empty());
+        assert!(found("\"ping\" OR").is_empty());
+
+        db.insert_link(link("Rust docs", "https://doc.rust-lang.org"))
+            .unwrap();
+        assert_eq!(db.search_links("DOC").unwrap()[0].title, "Rust docs");
+        assert!(db.search_links("rust-lang").unwrap().is_empty());
+        db.update_link("rust docs", link("Rust book", "https://doc.rust-lang.org"))
+            .unwrap();
+        assert!(db.search_links("docs").unwrap().is_empty());
+        assert_eq!(db.search_links("book").unwrap()[0].title, "Rust book");
+        db.delete_link("rust book").unwrap();
+        assert!(db.search_links("book").unwrap().is_empty());
+    }
+
+    #[test]
+    fn older_databases_get_the_search_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.sqlite");
+        let connection = Connection::open(&path).unwrap();
+        for step in &SCHEMA_MIGRATIONS[..CURRENT_VERSION as usize] {
+            connection.execute_batch(step).unwrap();
+        }
+        connection
+            .pragma_update(None, "user_version", CURRENT_VERSION)
+            .unwrap();
+        SqliteDB::insert_topic_row(&connection, &topic("List", "files")).unwrap();
+        drop(connection);
+
+        let db = SqliteDB::open(&path).unwrap();
+        assert_eq!(titles(&db.search_topics("list").unwrap()), ["List"]);
+    }
+
+    #[test]
+    fn json_library_is_imported_into_a_new_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("db.sqlite");
+        let json_path = dir.path().join("db.json");
+        fs::write(&json_path, "{ not json").unwrap();
+        assert!(matches!(
+            SqliteDB::init(&path, &json_path),
+            Err(TermnoteError::Parse(_))
+        ));
+        assert!(!path.exists());
+
+        fs::write(
+            &json_path,
+            r#"{"topics": [{"title": "List", "command": "ls"}]}"#,
+        )
+        .unwrap();
+        let db = SqliteDB::init(&path, &json_path).unwrap();
+        assert_eq!(titles(&db.library().topics), ["List"]);
+        fs::remove_file(&json_path).unwrap();
+        let reopened = SqliteDB::init(&path, &json_path).unwrap();
+        assert_eq!(titles(&reopened.library().topics), ["List"]);
+    }
+}
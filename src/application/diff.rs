@@ -0,0 +1,91 @@
+use colored::Colorize;
+
+use crate::{Library, Link, Topic};
+
+/// Titles of the entries which change when `current` is replaced by `target`.
+#[derive(Default)]
+pub struct LibraryDiff {
+    pub added_topics: Vec<String>,
+    pub removed_topics: Vec<String>,
+    pub changed_topics: Vec<String>,
+    pub added_links: Vec<String>,
+    pub removed_links: Vec<String>,
+    pub changed_links: Vec<String>,
+}
+
+impl LibraryDiff {
+    pub fn between(current: &Library, target: &Library) -> Self {
+        let (added_topics, removed_topics, changed_topics) =
+            diff_entries(&current.topics, &target.topics, |topic: &Topic| {
+                &topic.title
+            });
+        let (added_links, removed_links, changed_links) =
+            diff_entries(&current.links, &target.links, |link: &Link| &link.title);
+        Self {
+            added_topics,
+            removed_topics,
+            changed_topics,
+            added_links,
+            removed_links,
+            changed_links,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_topics.is_empty()
+            && self.removed_topics.is_empty()
+            && self.changed_topics.is_empty()
+            && self.added_links.is_empty()
+            && self.removed_links.is_empty()
+            && self.changed_links.is_empty()
+    }
+}
+
+type EntryChanges = (Vec<String>, Vec<String>, Vec<String>);
+
+fn diff_entries<T: PartialEq>(
+    current: &[T],
+    target: &[T],
+    title: impl Fn(&T) -> &String,
+) -> EntryChanges {
+    let same_title = |a: &T, b: &T| title(a).to_lowercase() == title(b).to_lowercase();
+    let mut added = Vec::new();
+    let mut changed = Vec::new();
+    for entry in target {
+        match current.iter().find(|other| same_title(other, entry)) {
+            None => added.push(title(entry).to_string()),
+            Some(other) if other != entry => changed.push(title(entry).to_string()),
+            Some(_) => {}
+        }
+    }
+    let removed = current
+        .iter()
+        .filter(|entry| !target.iter().any(|other| same_title(other, entry)))
+        .map(|entry| title(entry).to_string())
+        .collect();
+    (added, removed, changed)
+}
+
+pub fn deserialize_library_diff(diff: &LibraryDiff) -> String {
+    let mut diff_output: String = "".to_string();
+    let sections = [
+        ("+", "topic", &diff.added_topics),
+        ("-", "topic", &diff.removed_topics),
+        ("~", "topic", &diff.changed_topics),
+        ("+", "link", &diff.added_links),
+        ("-", "link", &diff.removed_links),
+        ("~", "link", &diff.changed_links),
+    ];
+    for (marker, kind, titles) in sections {
+        for title in titles {
+            let line = format!("{} {} {} \n", marker, kind, title);
+            let line = match marker {
+                "+" => line.bright_green(),
+                "-" => line.bright_red(),
+                _ => line.yellow(),
+            };
+            diff_output.push_str(line.to_string().as_str());
+        }
+    }
+    diff_output
+}
@@ -0,0 +1,288 @@
+use std::collections::HashSet;
+
+use crate::{generate_id, Library};
+
+/// A problem `tn doctor` found in the library.
+pub struct Finding {
+    pub problem: String,
+    /// What fixing the problem does, `None` if it has to be fixed by hand.
+    pub fix: Option<String>,
+}
+
+/// Problems found in a library together with the library after fixing them.
+pub struct Diagnosis {
+    pub findings: Vec<Finding>,
+    pub fixed: Library,
+}
+
+impl Diagnosis {
+    pub fn fixable(&self) -> usize {
+        self.findings
+            .iter()
+            .filter(|finding| finding.fix.is_some())
+            .count()
+    }
+}
+
+/// Checks the library for duplicate ids and entries, titles used more than
+/// once, topics without a command and links with an invalid URL.
+pub fn diagnose(library: &Library) -> Diagnosis {
+    let mut findings = Vec::new();
+    let mut fixed = Library {
+        topics: Vec::new(),
+        links: Vec::new(),
+        ..library.clone()
+    };
+    let mut ids: HashSet<String> = HashSet::new();
+    for topic in &library.topics {
+        let label = format!("Topic {} '{}'", topic.id, topic.title);
+        if topic.command.trim().is_empty() {
+            findings.push(Finding {
+                problem: format!("{} has an empty command", label),
+                fix: Some("remove it".to_string()),
+            });
+            continue;
+        }
+        if let Some(original) = fixed
+            .topics
+            .iter()
+            .find(|kept| kept.title == topic.title && kept.command == topic.command)
+        {
+            findings.push(Finding {
+                problem: format!("{} duplicates topic {}", label, original.id),
+                fix: Some("remove the duplicate".to_string()),
+            });
+            continue;
+        }
+        let mut topic = topic.clone();
+        if ids.contains(&topic.id) {
+            topic.id = generate_id(|id| ids.contains(id));
+            findings.push(Finding {
+                problem: format!("{} shares its ID with another topic", label),
+                fix: Some(format!("give it the ID {}", topic.id)),
+            });
+        }
+        let titles: Vec<&str> = fixed
+            .topics
+            .iter()
+            .map(|kept| kept.title.as_str())
+            .collect();
+        if let Some(title) = unique_title(&topic.title, &titles) {
+            findings.push(Finding {
+                problem: format!("{} has the same title as another topic", label),
+                fix: Some(format!("rename it to '{}'", title)),
+            });
+            topic.title = title;
+        }
+        ids.insert(topic.id.clone());
+        fixed.topics.push(topic);
+    }
+
+    ids.clear();
+    for link in &library.links {
+        let label = format!("Link {} '{}'", link.id, link.title);
+        if let Some(original) = fixed
+            .links
+            .iter()
+            .find(|kept| kept.title == link.title && kept.url == link.url)
+        {
+            findings.push(Finding {
+                problem: format!("{} duplicates link {}", label, original.id),
+                fix: Some("remove the duplicate".to_string()),
+            });
+            continue;
+        }
+        let mut link = link.clone();
+        if !is_valid_url(&link.url) {
+            let with_scheme = format!("https://{}", link.url.trim());
+            if link.url.trim().is_empty() {
+                findings.push(Finding {
+                    problem: format!("{} has no URL", label),
+                    fix: Some("remove it".to_string()),
+                });
+                continue;
+            } else if looks_like_host(link.url.trim()) && is_valid_url(&with_scheme) {
+                findings.push(Finding {
+                    problem: format!("{} has the URL '{}' without a scheme", label, link.url),
+                    fix: Some(format!("change it to '{}'", with_scheme)),
+                });
+                link.url = with_scheme;
+            } else {
+                findings.push(Finding {
+                    problem: format!("{} has the invalid URL '{}'", label, link.url),
+                    fix: None,
+                });
+            }
+        }
+        if ids.contains(&link.id) {
+            link.id = generate_id(|id| ids.contains(id));
+            findings.push(Finding {
+                problem: format!("{} shares its ID with another link", label),
+                fix: Some(format!("give it the ID {}", link.id)),
+            });
+        }
+        let titles: Vec<&str> = fixed.links.iter().map(|kept| kept.title.as_str()).collect();
+        if let Some(title) = unique_title(&link.title, &titles) {
+            findings.push(Finding {
+                problem: format!("{} has the same title as another link", label),
+                fix: Some(format!("rename it to '{}'", title)),
+            });
+            link.title = title;
+        }
+        ids.insert(link.id.clone());
+        fixed.links.push(link);
+    }
+    Diagnosis { findings, fixed }
+}
+
+/// A new title if `title` is already taken, titles being compared case-insensitively.
+fn unique_title(title: &str, taken: &[&str]) -> Option<String> {
+    let is_taken = |candidate: &str| {
+        taken
+            .iter()
+            .any(|other| other.to_lowercase() == candidate.to_lowercase())
+    };
+    if !is_taken(title) {
+        return None;
+    }
+    (2..)
+        .map(|number| format!("{} ({})", title, number))
+        .find(|candidate| !is_taken(candidate))
+}
+
+/// Whether the URL has a scheme and, when it names an authority (`scheme://`), a host
+/// unless it is a file URL. Schemes without an authority, like `mailto:` or `tel:`, only
+/// need something after the colon.
+fn is_valid_url(url: &str) -> bool {
+    let Some((scheme, rest)) = url.split_once(':') else {
+        return false;
+    };
+    let valid_scheme = scheme
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    let valid_rest = match rest.strip_prefix("//") {
+        Some(authority) => {
+            let host = authority.split(['/', '?', '#']).next().unwrap_or_default();
+            scheme == "file" || !host.is_empty()
+        }
+        None => !rest.is_empty(),
+    };
+    valid_scheme && valid_rest && !url.contains(char::is_whitespace)
+}
+
+/// Whether the string without a scheme starts with something that reads as a host name,
+/// like `docs.rs/std`.
+fn looks_like_host(url: &str) -> bool {
+    let name = url.split(['/', '?', '#']).next().unwrap_or_default();
+    !url.contains(char::is_whitespace)
+        && (name.contains('.') || name == "localhost")
+        && !name.starts_with('.')
+        && !name.ends_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+    use crate::{Link, Origin, Topic};
+
+    fn topic(id: &str, title: &str, command: &str) -> Topic {
+        Topic {
+            id: id.to_string(),
+            title: title.to_string(),
+            description: String::new(),
+            command: command.to_string(),
+            category: "misc".to_string(),
+            tags: Vec::new(),
+            steps: Vec::new(),
+            cwd: None,
+            env: Default::default(),
+            shell: None,
+            dangerous: false,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            last_used_at: None,
+            use_count: 0,
+            origin: Origin::Global,
+        }
+    }
+
+    fn link(id: &str, title: &str, url: &str) -> Link {
+        Link {
+            id: id.to_string(),
+            title: title.to_string(),
+            url: url.to_string(),
+            tags: Vec::new(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            last_used_at: None,
+            use_count: 0,
+            origin: Origin::Global,
+        }
+    }
+
+    #[test]
+    fn fixes_topics() {
+        let library = Library {
+            topics: vec![
+                topic("a", "List", "ls"),
+                topic("b", "List", "ls"),
+                topic("c", "Empty", " "),
+                topic("a", "list", "ls -la"),
+            ],
+            ..Library::default()
+        };
+        let diagnosis = diagnose(&library);
+        assert_eq!(diagnosis.findings.len(), 4);
+        assert_eq!(diagnosis.fixable(), 4);
+        let fixed = &diagnosis.fixed.topics;
+        assert_eq!(fixed.len(), 2);
+        assert_ne!(fixed[1].id, "a");
+        assert_eq!(fixed[1].title, "list (2)");
+        assert!(diagnose(&diagnosis.fixed).findings.is_empty());
+    }
+
+    #[test]
+    fn fixes_urls_without_a_scheme() {
+        let library = Library {
+            links: vec![
+                link("a", "Docs", "docs.rs"),
+                link("b", "Local", "file:///tmp/notes.txt"),
+                link("c", "Spaces", "not a url"),
+                link("d", "Blank", ""),
+                link("e", "Mail", "mailto:team@example.com"),
+                link("f", "Phone", "tel:+15550100"),
+                link("g", "Word", "notes"),
+            ],
+            ..Library::default()
+        };
+        let diagnosis = diagnose(&library);
+        assert_eq!(diagnosis.findings.len(), 4);
+        assert_eq!(diagnosis.fixable(), 2);
+        let urls: Vec<&str> = diagnosis
+            .fixed
+            .links
+            .iter()
+            .map(|link| link.url.as_str())
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                "https://docs.rs",
+                "file:///tmp/notes.txt",
+                "not a url",
+                "mailto:team@example.com",
+                "tel:+15550100",
+                "notes",
+            ]
+        );
+    }
+}
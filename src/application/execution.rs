@@ -0,0 +1,509 @@
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use execute::shell;
+
+use crate::{Shell, TermnoteError, Topic};
+
+/// Where and with what a command runs.
+#[derive(Clone, Default, Debug)]
+pub struct ExecContext {
+    /// Working directory, the current one if not set.
+    pub cwd: Option<PathBuf>,
+    pub env: BTreeMap<String, String>,
+    /// Shell to run the command with, the one in `$SHELL` if not set.
+    pub shell: Option<Shell>,
+    /// Terminates the command when it runs longer.
+    pub timeout: Option<Duration>,
+    /// Keeps the last part of the output, see [`run_cmd`].
+    pub capture: bool,
+}
+
+impl ExecContext {
+    /// Context the commands of the topic run in, a leading `~/` in its
+    /// working directory standing for the home directory.
+    pub fn of(topic: &Topic) -> Self {
+        let cwd = topic
+            .cwd
+            .as_ref()
+            .map(|cwd| match (cwd.strip_prefix("~/"), dirs::home_dir()) {
+                (Some(rest), Some(home)) => home.join(rest),
+                _ if cwd == "~" => dirs::home_dir().unwrap_or_else(|| PathBuf::from(cwd)),
+                _ => PathBuf::from(cwd),
+            });
+        Self {
+            cwd,
+            env: topic.env.clone(),
+            shell: topic.shell,
+            timeout: None,
+            capture: false,
+        }
+    }
+
+    /// The working directory the command runs in, resolving the current one.
+    pub fn resolved_cwd(&self) -> PathBuf {
+        match &self.cwd {
+            Some(cwd) => cwd.clone(),
+            None => std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+        }
+    }
+
+    /// The working directory, shell, environment and timeout the command
+    /// would run with, one per line.
+    pub fn describe(&self) -> String {
+        let cwd = self.resolved_cwd().display().to_string();
+        let shell = match self.shell {
+            Some(shell) => shell.name().to_string(),
+            None => std::env::var("SHELL").unwrap_or_else(|_| "sh".to_string()),
+        };
+        let mut lines = vec![format!("cwd: {}", cwd), format!("shell: {}", shell)];
+        lines.extend(
+            self.env
+                .iter()
+                .map(|(name, value)| format!("env: {}={}", name, value)),
+        );
+        if let Some(timeout) = self.timeout {
+            lines.push(format!("timeout: {}s", timeout.as_secs()));
+        }
+        lines.join("\n")
+    }
+
+    fn command(&self, cmd_str: &str) -> Result<Command, TermnoteError> {
+        let mut command = match self.shell {
+            Some(name) => {
+                let mut command = Command::new(name.name());
+                command.arg("-c").arg(cmd_str);
+                command
+            }
+            None => shell(cmd_str),
+        };
+        if let Some(cwd) = &self.cwd {
+            if !cwd.is_dir() {
+                return Err(TermnoteError::Validation(format!(
+                    "The working directory {} does not exist",
+                    cwd.display()
+                )));
+            }
+            command.current_dir(cwd);
+        }
+        command.envs(&self.env);
+        Ok(command)
+    }
+}
+
+/// How an executed command ended.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Outcome {
+    pub status: ExitStatus,
+    /// Whether the command was killed because it exceeded the timeout.
+    pub timed_out: bool,
+    /// Last part of the output, `None` unless it was captured.
+    pub output: Option<String>,
+    /// Whether the beginning of the output was cut off.
+    pub output_truncated: bool,
+}
+
+impl Outcome {
+    pub fn success(&self) -> bool {
+        self.status.success() && !self.timed_out
+    }
+
+    /// Exit code to report, 124 for a timeout like the `timeout` utility.
+    pub fn exit_code(&self) -> i32 {
+        if self.timed_out {
+            TIMEOUT_EXIT_CODE
+        } else {
+            exit_code(self.status)
+        }
+    }
+}
+
+const TIMEOUT_EXIT_CODE: i32 = 124;
+/// Time a command gets to exit after the timeout before it is killed for good.
+const KILL_GRACE: Duration = Duration::from_secs(2);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Bytes of captured output which are kept, the end of the output being the
+/// most telling part.
+const CAPTURE_LIMIT: usize = 4096;
+
+/// Runs the command in the shell with the terminal inherited, so that its
+/// output and errors show up as they are written, whatever their encoding,
+/// and interactive programs like editors work. The command runs in its own
+/// process group, which is terminated on timeout and receives the signals
+/// interrupting `tn`, e.g. Ctrl-C.
+///
+/// With `capture` set the output goes through pipes instead, which are copied
+/// to the terminal as they are read while their last part is kept. Programs
+/// then no longer write to a terminal, so they may e.g. drop their colors.
+pub fn run_cmd(cmd_str: &str, context: &ExecContext) -> Result<Outcome, TermnoteError> {
+    let mut command = context.command(cmd_str)?;
+    if context.capture {
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+    }
+    let signals = platform::forward_signals()?;
+    let outcome = platform::spawn(&mut command).and_then(|mut child| {
+        let capture = Capture::start(&mut child);
+        let outcome = wait(&mut child, context.timeout, signals);
+        platform::release_terminal();
+        let (output, output_truncated) = capture.finish();
+        outcome.map(|outcome| Outcome {
+            output,
+            output_truncated,
+            ..outcome
+        })
+    });
+    signals.stop();
+    Ok(outcome?)
+}
+
+fn wait(
+    child: &mut Child,
+    timeout: Option<Duration>,
+    signals: &platform::Signals,
+) -> io::Result<Outcome> {
+    let started = Instant::now();
+    let mut terminated_at: Option<Instant> = None;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Outcome {
+                status,
+                timed_out: terminated_at.is_some(),
+                output: None,
+                output_truncated: false,
+            });
+        }
+        signals.forward(child);
+        match terminated_at {
+            None if timeout.is_some_and(|timeout| started.elapsed() >= timeout) => {
+                platform::terminate(child);
+                terminated_at = Some(Instant::now());
+            }
+            Some(at) if at.elapsed() >= KILL_GRACE => {
+                child.kill()?;
+                platform::kill_group(child);
+            }
+            _ => {}
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Last bytes of the output read so far.
+#[derive(Default)]
+struct Tail {
+    bytes: Vec<u8>,
+    truncated: bool,
+}
+
+impl Tail {
+    fn push(&mut self, chunk: &[u8]) {
+        self.bytes.extend_from_slice(chunk);
+        // cut off in batches rather than on every chunk
+        if self.bytes.len() > 2 * CAPTURE_LIMIT {
+            self.cut();
+        }
+    }
+
+    fn cut(&mut self) {
+        let excess = self.bytes.len().saturating_sub(CAPTURE_LIMIT);
+        if excess > 0 {
+            self.bytes.drain(..excess);
+            self.truncated = true;
+        }
+    }
+}
+
+/// Copies the piped output of a command to the terminal, keeping its tail.
+struct Capture {
+    tail: Arc<Mutex<Tail>>,
+    readers: Vec<JoinHandle<()>>,
+}
+
+impl Capture {
+    fn start(child: &mut Child) -> Self {
+        let tail = Arc::new(Mutex::new(Tail::default()));
+        let mut readers = Vec::new();
+        if let Some(stdout) = child.stdout.take() {
+            let tail = Arc::clone(&tail);
+            readers.push(thread::spawn(move || tee(stdout, io::stdout(), &tail)));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            let tail = Arc::clone(&tail);
+            readers.push(thread::spawn(move || tee(stderr, io::stderr(), &tail)));
+        }
+        Self { tail, readers }
+    }
+
+    /// Waits for the output to end and returns its tail, `None` if nothing
+    /// was captured.
+    fn finish(self) -> (Option<String>, bool) {
+        if self.readers.is_empty() {
+            return (None, false);
+        }
+        for reader in self.readers {
+            let _ = reader.join();
+        }
+        let mut tail = self.tail.lock().unwrap_or_else(|e| e.into_inner());
+        tail.cut();
+        // the cut may have split a character
+        let start = if tail.truncated {
+            tail.bytes
+                .iter()
+                .position(|byte| byte & 0xC0 != 0x80)
+                .unwrap_or(tail.bytes.len())
+        } else {
+            0
+        };
+        let output = String::from_utf8_lossy(&tail.bytes[start..]).to_string();
+        (Some(output), tail.truncated)
+    }
+}
+
+fn tee(mut source: impl Read, mut sink: impl Write, tail: &Mutex<Tail>) {
+    let mut buffer = [0; 8192];
+    loop {
+        let read = match source.read(&mut buffer) {
+            Ok(0) => return,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(_) => return,
+        };
+        let _ = sink.write_all(&buffer[..read]).and_then(|_| sink.flush());
+        tail.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(&buffer[..read]);
+    }
+}
+
+#[cfg(unix)]
+mod platform {
+    use std::io;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, OnceLock};
+
+    use libc::{c_int, pid_t, SIGHUP, SIGINT, SIGKILL, SIGQUIT, SIGTERM, SIGTTOU, STDIN_FILENO};
+    use signal_hook::flag;
+
+    const FORWARDED: [c_int; 4] = [SIGINT, SIGTERM, SIGHUP, SIGQUIT];
+
+    /// Signals received by `tn` while a command runs, which are passed on to
+    /// the command instead of terminating `tn`.
+    pub struct Signals {
+        pending: Vec<(c_int, Arc<AtomicBool>)>,
+        /// Set while no command runs, the signals then take their default action.
+        idle: Arc<AtomicBool>,
+    }
+
+    impl Signals {
+        pub fn forward(&self, child: &Child) {
+            for (signal, received) in &self.pending {
+                if received.swap(false, Ordering::SeqCst) {
+                    signal_group(child, *signal);
+                }
+            }
+        }
+
+        pub fn stop(&self) {
+            self.idle.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Registers the handlers once, as they cannot be removed again.
+    pub fn forward_signals() -> io::Result<&'static Signals> {
+        static SIGNALS: OnceLock<Signals> = OnceLock::new();
+        if let Some(signals) = SIGNALS.get() {
+            signals.idle.store(false, Ordering::SeqCst);
+            return Ok(signals);
+        }
+        let idle = Arc::new(AtomicBool::new(false));
+        let mut pending = Vec::new();
+        for signal in FORWARDED {
+            let received = Arc::new(AtomicBool::new(false));
+            flag::register(signal, Arc::clone(&received))?;
+            flag::register_conditional_default(signal, Arc::clone(&idle))?;
+            pending.push((signal, received));
+        }
+        Ok(SIGNALS.get_or_init(|| Signals { pending, idle }))
+    }
+
+    /// Starts the command in a new process group, which gets the terminal if
+    /// `tn` has it, so that interactive commands and Ctrl-C work as usual.
+    pub fn spawn(command: &mut Command) -> io::Result<Child> {
+        command.process_group(0);
+        let foreground = owns_terminal();
+        if foreground {
+            // both sides hand over the terminal, whichever runs first
+            unsafe {
+                command.pre_exec(|| {
+                    set_foreground(libc::getpid());
+                    Ok(())
+                });
+            }
+        }
+        let child = command.spawn()?;
+        if foreground {
+            set_foreground(child.id() as pid_t);
+        }
+        Ok(child)
+    }
+
+    /// Takes the terminal back after the command ended.
+    pub fn release_terminal() {
+        let own_group = unsafe { libc::getpgrp() };
+        if unsafe { libc::isatty(STDIN_FILENO) } == 1
+            && unsafe { libc::tcgetpgrp(STDIN_FILENO) } != own_group
+        {
+            set_foreground(own_group);
+        }
+    }
+
+    pub fn terminate(child: &Child) {
+        signal_group(child, SIGTERM);
+    }
+
+    pub fn kill_group(child: &Child) {
+        signal_group(child, SIGKILL);
+    }
+
+    fn signal_group(child: &Child, signal: c_int) {
+        unsafe {
+            libc::killpg(child.id() as pid_t, signal);
+        }
+    }
+
+    fn owns_terminal() -> bool {
+        unsafe {
+            libc::isatty(STDIN_FILENO) == 1 && libc::tcgetpgrp(STDIN_FILENO) == libc::getpgrp()
+        }
+    }
+
+    /// Makes the process group the foreground one of the terminal. A process
+    /// in a background group doing so would be stopped by SIGTTOU, which is
+    /// blocked meanwhile.
+    fn set_foreground(group: pid_t) {
+        unsafe {
+            let mut blocked: libc::sigset_t = std::mem::zeroed();
+            let mut previous: libc::sigset_t = std::mem::zeroed();
+            libc::sigemptyset(&mut blocked);
+            libc::sigaddset(&mut blocked, SIGTTOU);
+            libc::pthread_sigmask(libc::SIG_BLOCK, &blocked, &mut previous);
+            libc::tcsetpgrp(STDIN_FILENO, group);
+            libc::pthread_sigmask(libc::SIG_SETMASK, &previous, std::ptr::null_mut());
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod platform {
+    use std::io;
+    use std::process::{Child, Command};
+
+    pub struct Signals;
+
+    impl Signals {
+        pub fn forward(&self, _child: &Child) {}
+
+        pub fn stop(&self) {}
+    }
+
+    pub fn forward_signals() -> io::Result<&'static Signals> {
+        Ok(&Signals)
+    }
+
+    pub fn spawn(command: &mut Command) -> io::Result<Child> {
+        command.spawn()
+    }
+
+    pub fn release_terminal() {}
+
+    pub fn terminate(child: &mut Child) {
+        let _ = child.kill();
+    }
+
+    pub fn kill_group(_child: &Child) {}
+}
+
+/// Exit code of a finished command, following the shell convention of 128
+/// plus the signal number for commands killed by a signal.
+#[cfg(unix)]
+pub fn exit_code(status: ExitStatus) -> i32 {
+    use std::os::unix::process::ExitStatusExt;
+
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+#[cfg(not(unix))]
+pub fn exit_code(status: ExitStatus) -> i32 {
+    status.code().unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(cmd_str: &str) -> Outcome {
+        run_cmd(cmd_str, &ExecContext::default()).unwrap()
+    }
+
+    #[test]
+    fn commands_exit_with_their_own_status() {
+        assert_eq!(run("true").exit_code(), 0);
+        assert_eq!(run("exit 3").exit_code(), 3);
+        assert_eq!(run("missing-termnote-command").exit_code(), 127);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn killed_commands_exit_with_128_plus_the_signal() {
+        assert_eq!(run("kill -9 $$").exit_code(), 137);
+    }
+
+    #[test]
+    fn commands_run_in_the_context_of_the_topic() {
+        let dir = tempfile::tempdir().unwrap();
+        let context = ExecContext {
+            cwd: Some(dir.path().to_path_buf()),
+            env: BTreeMap::from([("GREETING".to_string(), "hi".to_string())]),
+            shell: Some(Shell::Sh),
+            ..ExecContext::default()
+        };
+        let check = format!(
+            "test \"$(pwd -P)\" = \"{}\" && test \"$GREETING\" = hi",
+            dir.path().canonicalize().unwrap().display()
+        );
+        assert!(run_cmd(&check, &context).unwrap().success());
+
+        let missing = ExecContext {
+            cwd: Some(dir.path().join("missing")),
+            ..ExecContext::default()
+        };
+        assert!(matches!(
+            run_cmd("true", &missing),
+            Err(TermnoteError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn commands_running_too_long_are_terminated() {
+        let context = ExecContext {
+            timeout: Some(Duration::from_millis(200)),
+            ..ExecContext::default()
+        };
+        let started = Instant::now();
+        let outcome = run_cmd("sleep 10", &context).unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(outcome.timed_out && !outcome.success());
+        assert_eq!(outcome.exit_code(), 124);
+        assert!(run_cmd("true", &context).unwrap().success());
+    }
+}
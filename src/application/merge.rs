@@ -0,0 +1,271 @@
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{Library, Link, Topic};
+
+/// Accessors the structural merge needs on topics and links.
+trait MergeEntry: Clone + PartialEq + Serialize + DeserializeOwned {
+    fn id(&self) -> &str;
+    fn updated_at(&self) -> DateTime<Utc>;
+    fn usage(&self) -> (u64, Option<DateTime<Utc>>);
+    fn set_metadata(
+        &mut self,
+        updated_at: DateTime<Utc>,
+        use_count: u64,
+        last_used_at: Option<DateTime<Utc>>,
+    );
+}
+
+impl MergeEntry for Topic {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    fn usage(&self) -> (u64, Option<DateTime<Utc>>) {
+        (self.use_count, self.last_used_at)
+    }
+
+    fn set_metadata(
+        &mut self,
+        updated_at: DateTime<Utc>,
+        use_count: u64,
+        last_used_at: Option<DateTime<Utc>>,
+    ) {
+        self.updated_at = updated_at;
+        self.use_count = use_count;
+        self.last_used_at = last_used_at;
+    }
+}
+
+impl MergeEntry for Link {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    fn usage(&self) -> (u64, Option<DateTime<Utc>>) {
+        (self.use_count, self.last_used_at)
+    }
+
+    fn set_metadata(
+        &mut self,
+        updated_at: DateTime<Utc>,
+        use_count: u64,
+        last_used_at: Option<DateTime<Utc>>,
+    ) {
+        self.updated_at = updated_at;
+        self.use_count = use_count;
+        self.last_used_at = last_used_at;
+    }
+}
+
+/// Three-way merge of two libraries which diverged from `base`, matching
+/// entries by id. Changes made on one side only are taken over. When both
+/// sides changed an entry the fields are merged one by one, the latest edit
+/// winning for a field changed on both sides, an edit wins over a removal
+/// and the usage counters of both sides are added up.
+pub fn merge_libraries(base: &Library, ours: &Library, theirs: &Library) -> Library {
+    Library {
+        topics: merge_entries(&base.topics, &ours.topics, &theirs.topics),
+        links: merge_entries(&base.links, &ours.links, &theirs.links),
+        ..Library::default()
+    }
+}
+
+fn merge_entries<T: MergeEntry>(base: &[T], ours: &[T], theirs: &[T]) -> Vec<T> {
+    let find = |entries: &'_ [T], id: &str| -> Option<T> {
+        entries.iter().find(|entry| entry.id() == id).cloned()
+    };
+    // our order first, followed by the entries only known to the other side
+    let mut ids: Vec<&str> = ours.iter().map(MergeEntry::id).collect();
+    for entry in theirs {
+        if !ids.contains(&entry.id()) {
+            ids.push(entry.id());
+        }
+    }
+    ids.into_iter()
+        .filter_map(|id| merge_entry(find(base, id), find(ours, id), find(theirs, id)))
+        .collect()
+}
+
+fn merge_entry<T: MergeEntry>(base: Option<T>, ours: Option<T>, theirs: Option<T>) -> Option<T> {
+    if ours == theirs || theirs == base {
+        return ours;
+    }
+    if ours == base {
+        return theirs;
+    }
+    match (base, ours, theirs) {
+        (Some(base), Some(ours), Some(theirs)) => Some(merge_fields(&base, &ours, &theirs)),
+        (_, Some(ours), Some(theirs)) => Some(newer(ours, theirs)),
+        (_, ours, theirs) => ours.or(theirs),
+    }
+}
+
+/// Merges an entry changed on both sides field by field.
+fn merge_fields<T: MergeEntry>(base: &T, ours: &T, theirs: &T) -> T {
+    let theirs_newer = theirs.updated_at() > ours.updated_at();
+    let (Some(base_fields), Some(our_fields), Some(their_fields)) =
+        (fields(base), fields(ours), fields(theirs))
+    else {
+        return newer(ours.clone(), theirs.clone());
+    };
+    let mut merged_fields = our_fields.clone();
+    for (key, their_value) in &their_fields {
+        let base_value = base_fields.get(key);
+        let our_value = our_fields.get(key);
+        let their_change = Some(their_value) != base_value;
+        if our_value == base_value || (theirs_newer && their_change) {
+            merged_fields.insert(key.clone(), their_value.clone());
+        }
+    }
+    let mut merged: T = match serde_json::from_value(Value::Object(merged_fields)) {
+        Ok(merged) => merged,
+        Err(_) => return newer(ours.clone(), theirs.clone()),
+    };
+    let (base_count, _) = base.usage();
+    let (our_count, our_last_used) = ours.usage();
+    let (their_count, their_last_used) = theirs.usage();
+    merged.set_metadata(
+        ours.updated_at().max(theirs.updated_at()),
+        (our_count + their_count).saturating_sub(base_count),
+        our_last_used.max(their_last_used),
+    );
+    merged
+}
+
+fn fields<T: Serialize>(entry: &T) -> Option<Map<String, Value>> {
+    match serde_json::to_value(entry) {
+        Ok(Value::Object(fields)) => Some(fields),
+        _ => None,
+    }
+}
+
+fn newer<T: MergeEntry>(ours: T, theirs: T) -> T {
+    if theirs.updated_at() > ours.updated_at() {
+        theirs
+    } else {
+        ours
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(minute: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(minute * 60, 0).unwrap()
+    }
+
+    fn topic(id: &str, title: &str) -> Topic {
+        Topic {
+            id: id.to_string(),
+            title: title.to_string(),
+            description: String::new(),
+            command: "ls".to_string(),
+            category: "files".to_string(),
+            tags: Vec::new(),
+            steps: Vec::new(),
+            cwd: None,
+            env: Default::default(),
+            shell: None,
+            dangerous: false,
+            created_at: at(0),
+            updated_at: at(0),
+            last_used_at: None,
+            use_count: 0,
+            origin: Default::default(),
+        }
+    }
+
+    fn library(topics: Vec<Topic>) -> Library {
+        Library {
+            topics,
+            ..Library::default()
+        }
+    }
+
+    fn titles(library: &Library) -> Vec<&str> {
+        library
+            .topics
+            .iter()
+            .map(|topic| topic.title.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn takes_over_changes_of_either_side() {
+        let base = library(vec![topic("a", "a"), topic("b", "b"), topic("c", "c")]);
+        let mut renamed = topic("a", "renamed");
+        renamed.updated_at = at(1);
+        let ours = library(vec![
+            renamed,
+            topic("b", "b"),
+            topic("c", "c"),
+            topic("d", "d"),
+        ]);
+        let theirs = library(vec![topic("a", "a"), topic("c", "c"), topic("e", "e")]);
+        let merged = merge_libraries(&base, &ours, &theirs);
+        assert_eq!(titles(&merged), ["renamed", "c", "d", "e"]);
+    }
+
+    #[test]
+    fn merges_an_entry_changed_on_both_sides_by_field() {
+        let base = library(vec![topic("a", "a")]);
+        let mut ours = topic("a", "ours");
+        ours.command = "ls -la".to_string();
+        ours.updated_at = at(1);
+        ours.use_count = 2;
+        ours.last_used_at = Some(at(1));
+        let mut theirs = topic("a", "theirs");
+        theirs.category = "disk".to_string();
+        theirs.updated_at = at(2);
+        theirs.use_count = 3;
+        theirs.last_used_at = Some(at(2));
+        let merged = merge_libraries(&base, &library(vec![ours]), &library(vec![theirs]));
+        let topic = &merged.topics[0];
+        assert_eq!(topic.title, "theirs");
+        assert_eq!(topic.command, "ls -la");
+        assert_eq!(topic.category, "disk");
+        assert_eq!(topic.updated_at, at(2));
+        assert_eq!(topic.use_count, 5);
+        assert_eq!(topic.last_used_at, Some(at(2)));
+    }
+
+    #[test]
+    fn an_edit_wins_over_a_removal() {
+        let base = library(vec![topic("a", "a")]);
+        let mut edited = topic("a", "edited");
+        edited.updated_at = at(1);
+        let edits = library(vec![edited]);
+        let removal = library(Vec::new());
+        assert_eq!(
+            titles(&merge_libraries(&base, &edits, &removal)),
+            ["edited"]
+        );
+        assert_eq!(
+            titles(&merge_libraries(&base, &removal, &edits)),
+            ["edited"]
+        );
+        assert!(merge_libraries(&base, &removal, &base).topics.is_empty());
+    }
+
+    #[test]
+    fn entries_added_on_both_sides_keep_the_newer_one() {
+        let base = library(Vec::new());
+        let ours = topic("a", "ours");
+        let mut theirs = topic("a", "theirs");
+        theirs.updated_at = at(1);
+        let merged = merge_libraries(&base, &library(vec![ours]), &library(vec![theirs]));
+        assert_eq!(titles(&merged), ["theirs"]);
+    }
+}
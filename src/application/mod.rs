@@ -1,6 +1,43 @@
+mod diff;
+mod doctor;
+mod execution;
+mod merge;
 mod processor;
+mod template;
+mod workflow;
 
+pub use diff::deserialize_library_diff;
+pub use diff::LibraryDiff;
+
+pub use doctor::diagnose;
+pub use doctor::Diagnosis;
+pub use doctor::Finding;
+
+pub use execution::exit_code;
+pub use execution::run_cmd;
+pub use execution::ExecContext;
+pub use execution::Outcome;
+
+pub use merge::merge_libraries;
+
+pub use processor::deserialize_backups;
+pub use processor::deserialize_execution;
+pub use processor::deserialize_executions;
 pub use processor::deserialize_links;
 pub use processor::deserialize_topics;
-pub use processor::run_cmd;
+pub use processor::sort_links;
+pub use processor::sort_topics;
 pub use processor::CRUDProcessor;
+pub use processor::SortOrder;
+pub use processor::TopicDetails;
+
+pub use template::CommandTemplate;
+pub use template::Placeholder;
+
+pub use workflow::fill_steps;
+pub use workflow::run_steps;
+pub use workflow::step_label;
+pub use workflow::summarize_steps;
+pub use workflow::StepAnswer;
+pub use workflow::StepResult;
+pub use workflow::StepStatus;
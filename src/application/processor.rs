@@ -1,88 +1,460 @@
+use chrono::{Local, TimeDelta, Utc};
 use colored::Colorize;
-use execute::{shell, Execute};
-use std::process::Stdio;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::path::Path;
 
-use crate::{Link, ObjectDB, Topic};
+use crate::adapter::{is_encrypted, normalize_tags, sibling_path, FileLock, LOCAL_FILE};
+use crate::application::{
+    diagnose, merge_libraries, run_cmd, Diagnosis, ExecContext, LibraryDiff, Outcome,
+};
+use crate::{
+    display_warning, generate_id, Backend, Backup, BackupStore, Change, Cipher, Config, Execution,
+    ExecutionLog, GitSync, Journal, JournalEntry, Library, Link, MemoryDB, ObjectDB, Origin,
+    Repository, Shell, Step, TermnoteError, Topic,
+};
+
+/// Optional parts of a topic. On update, fields left `None` are kept and
+/// `Some(None)` removes the working directory or shell.
+#[derive(Default)]
+pub struct TopicDetails {
+    pub tags: Option<Vec<String>>,
+    pub steps: Option<Vec<Step>>,
+    pub cwd: Option<Option<String>>,
+    pub env: Option<BTreeMap<String, String>>,
+    pub shell: Option<Option<Shell>>,
+    pub dangerous: Option<bool>,
+}
+
+impl TopicDetails {
+    fn is_empty(&self) -> bool {
+        self.tags.is_none()
+            && self.steps.is_none()
+            && self.cwd.is_none()
+            && self.env.is_none()
+            && self.shell.is_none()
+            && self.dangerous.is_none()
+    }
+}
 
 pub struct CRUDProcessor {
-    pub database: ObjectDB,
+    pub database: Box<dyn Repository>,
+    /// Project library layered over the global `database`.
+    local: Option<ObjectDB>,
+    /// Read-only team libraries, shadowed by the personal ones.
+    shared: Vec<MemoryDB>,
+    backups: Option<BackupStore>,
+    journal: Option<Journal>,
+    executions: Option<ExecutionLog>,
+    sync: Option<GitSync>,
+    /// Encryption of the global library and everything derived from it.
+    cipher: Option<Cipher>,
 }
 
 impl CRUDProcessor {
-    pub fn init() -> Self {
-        let object_db = ObjectDB::init();
+    pub fn init(config: &Config) -> Result<Self, TermnoteError> {
+        let mut processor = match config.backend {
+            Backend::Sqlite => Self::init_sqlite(config)?,
+            Backend::Json => {
+                let cipher = Cipher::unlock(&config.db_path, &config.key_source())?;
+                let database = ObjectDB::init_with_cipher(&config.db_path, cipher.clone())?;
+                let mut processor = Self::with_repository(Box::new(database));
+                processor.cipher = cipher;
+                processor
+            }
+        };
+        if let Some(local_path) = &config.local_db_path {
+            processor.local = Some(ObjectDB::init(local_path)?);
+        }
+        for shared_path in config.shared_library_paths()? {
+            // a broken shared library must not keep the own one from being used
+            match ObjectDB::read_library(&shared_path) {
+                Ok(library) => processor.shared.push(MemoryDB::new(library)),
+                Err(e) => display_warning(&format!("Skipped a shared library: {}", e)),
+            }
+        }
+        processor.backups = Some(
+            BackupStore::new(
+                &config.backup_dir(),
+                &config.library_key(),
+                config.settings.backup_count,
+            )
+            .with_interval(backup_interval(config.backend))
+            .with_cipher(processor.cipher.clone()),
+        );
+        processor.journal = Some(
+            Journal::new(&config.journal_path(), config.settings.history_depth)
+                .with_cipher(processor.cipher.clone()),
+        );
+        processor.executions = Some(
+            ExecutionLog::new(
+                &config.execution_log_path(),
+                config.settings.execution_history,
+            )
+            .with_cipher(processor.cipher.clone()),
+        );
+        if config.backend == Backend::Json {
+            if let Ok(library_file) = config.db_path.strip_prefix(&config.home) {
+                processor.sync = Some(GitSync::new(&config.home, library_file));
+            }
+        }
+        Ok(processor)
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn init_sqlite(config: &Config) -> Result<Self, TermnoteError> {
+        let sqlite_db = crate::SqliteDB::init(&config.db_path, &config.json_path())?;
+        Ok(Self::with_repository(Box::new(sqlite_db)))
+    }
+
+    #[cfg(not(feature = "sqlite"))]
+    fn init_sqlite(_config: &Config) -> Result<Self, TermnoteError> {
+        Err(TermnoteError::Config(
+            "termnote was built without SQLite support, enable the `sqlite` feature".to_string(),
+        ))
+    }
+
+    pub fn with_repository(database: Box<dyn Repository>) -> Self {
         Self {
-            database: object_db,
+            database,
+            local: None,
+            shared: Vec::new(),
+            backups: None,
+            journal: None,
+            executions: None,
+            sync: None,
+            cipher: None,
+        }
+    }
+
+    /// Layers the project library at `path` over the global one, creating it
+    /// if it does not exist yet.
+    pub fn open_local(&mut self, path: &Path) -> Result<(), TermnoteError> {
+        if self
+            .local
+            .as_ref()
+            .is_some_and(|local| local.path() == path)
+        {
+            return Ok(());
+        }
+        self.local = Some(ObjectDB::init(path)?);
+        Ok(())
+    }
+
+    /// Takes the database locks and reloads the libraries so that a following
+    /// modification applies to the latest state and is not clobbered by, or
+    /// clobbering, concurrent invocations. The locks are released on drop.
+    fn lock_and_reload(&mut self) -> Result<Vec<FileLock>, TermnoteError> {
+        let mut locks = Vec::new();
+        if let Some(path) = self.database.lock_path() {
+            locks.push(FileLock::acquire(&path)?);
+        }
+        self.database.reload()?;
+        if let Some(local) = &mut self.local {
+            if let Some(path) = local.lock_path() {
+                locks.push(FileLock::acquire(&path)?);
+            }
+            local.reload()?;
+        }
+        Ok(locks)
+    }
+
+    fn store(&self, origin: Origin) -> Result<&dyn Repository, TermnoteError> {
+        match origin {
+            Origin::Global => Ok(self.database.as_ref()),
+            Origin::Local => self
+                .local
+                .as_ref()
+                .map(|local| local as &dyn Repository)
+                .ok_or_else(missing_local),
+            Origin::Shared => Err(read_only()),
+        }
+    }
+
+    fn store_mut(&mut self, origin: Origin) -> Result<&mut dyn Repository, TermnoteError> {
+        match origin {
+            Origin::Global => Ok(self.database.as_mut()),
+            Origin::Local => self
+                .local
+                .as_mut()
+                .map(|local| local as &mut dyn Repository)
+                .ok_or_else(missing_local),
+            Origin::Shared => Err(read_only()),
+        }
+    }
+
+    /// Snapshots the global library before it gets modified. Project libraries
+    /// are usually versioned along with their project and are not backed up.
+    fn backup(&self, origin: Origin) -> Result<(), TermnoteError> {
+        if let (Some(backups), Origin::Global) = (&self.backups, origin) {
+            backups.create(self.database.library())?;
+        }
+        Ok(())
+    }
+
+    fn record(
+        &self,
+        description: String,
+        change: Change,
+        origin: Origin,
+    ) -> Result<(), TermnoteError> {
+        if let Some(journal) = &self.journal {
+            let library = match origin {
+                Origin::Local => self.local.as_ref().map(|local| local.path().to_path_buf()),
+                _ => None,
+            };
+            journal.record(JournalEntry {
+                description: description.clone(),
+                change,
+                library,
+            })?;
+        }
+        if origin == Origin::Global {
+            self.commit(&description)?;
+        }
+        Ok(())
+    }
+
+    /// Commits the global library when it is synced through git.
+    fn commit(&self, message: &str) -> Result<(), TermnoteError> {
+        if let Some(sync) = self.sync.as_ref().filter(|sync| sync.is_initialized()) {
+            sync.commit(message)?;
+        }
+        Ok(())
+    }
+
+    /// Libraries in lookup order: the project library shadows the global one,
+    /// which shadows the shared ones.
+    fn layers(&self) -> Vec<(Origin, &dyn Repository)> {
+        let mut layers: Vec<(Origin, &dyn Repository)> = Vec::new();
+        if let Some(local) = &self.local {
+            layers.push((Origin::Local, local));
+        }
+        layers.push((Origin::Global, self.database.as_ref()));
+        for shared in &self.shared {
+            layers.push((Origin::Shared, shared));
+        }
+        layers
+    }
+
+    /// Topics of all libraries, marked with the library they come from.
+    pub fn topics(&self) -> Vec<Topic> {
+        self.layers()
+            .into_iter()
+            .flat_map(|(origin, store)| {
+                store.library().topics.iter().map(move |topic| Topic {
+                    origin,
+                    ..topic.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// Links of all libraries, marked with the library they come from.
+    pub fn links(&self) -> Vec<Link> {
+        self.layers()
+            .into_iter()
+            .flat_map(|(origin, store)| {
+                store.library().links.iter().map(move |link| Link {
+                    origin,
+                    ..link.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// Tags of all libraries with the number of topics and links carrying
+    /// them, sorted by name.
+    pub fn tag_counts(&self) -> Vec<(String, usize)> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        let topic_tags = self.topics().into_iter().flat_map(|topic| topic.tags);
+        let link_tags = self.links().into_iter().flat_map(|link| link.tags);
+        for tag in topic_tags.chain(link_tags) {
+            *counts.entry(tag).or_default() += 1;
         }
+        counts.into_iter().collect()
     }
 
-    pub fn add_url(&mut self, title: String, url: String) -> String {
+    /// Finds a topic by its id or, failing that, by its title.
+    pub fn find_topic(&self, key: &str) -> Result<Topic, TermnoteError> {
+        let topics = self.topics();
+        topics
+            .iter()
+            .find(|topic| topic.id == key)
+            .or_else(|| {
+                topics
+                    .iter()
+                    .find(|topic| topic.title.to_lowercase() == key.to_lowercase())
+            })
+            .cloned()
+            .ok_or_else(|| TermnoteError::NotFound(format!("Could not find topic {}", key)))
+    }
+
+    /// Finds a link by its id or, failing that, by its title.
+    pub fn find_link(&self, key: &str) -> Result<Link, TermnoteError> {
+        let links = self.links();
+        links
+            .iter()
+            .find(|link| link.id == key)
+            .or_else(|| {
+                links
+                    .iter()
+                    .find(|link| link.title.to_lowercase() == key.to_lowercase())
+            })
+            .cloned()
+            .ok_or_else(|| TermnoteError::NotFound(format!("Could not find link {}", key)))
+    }
+
+    fn topic_position(&self, origin: Origin, id: &str) -> Result<usize, TermnoteError> {
+        let topics = &self.store(origin)?.library().topics;
+        Ok(topics
+            .iter()
+            .position(|topic| topic.id == id)
+            .unwrap_or(topics.len()))
+    }
+
+    fn link_position(&self, origin: Origin, id: &str) -> Result<usize, TermnoteError> {
+        let links = &self.store(origin)?.library().links;
+        Ok(links
+            .iter()
+            .position(|link| link.id == id)
+            .unwrap_or(links.len()))
+    }
+
+    /// Adds a link to the library of `origin` and returns its id.
+    pub fn add_url(
+        &mut self,
+        title: String,
+        url: String,
+        tags: Vec<String>,
+        origin: Origin,
+    ) -> Result<String, TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let links = &self.store(origin)?.library().links;
+        if links
+            .iter()
+            .any(|link| link.title.to_lowercase() == title.to_lowercase())
+        {
+            return Err(TermnoteError::Duplicate(
+                "Link with that title already exists".to_string(),
+            ));
+        }
+        let all_links = self.links();
+        let now = Utc::now();
         let link_entry = Link {
-            title: title.clone(),
+            id: generate_id(|id| all_links.iter().any(|link| link.id == id)),
+            title,
             url,
+            tags: normalize_tags(&tags),
+            created_at: now,
+            updated_at: now,
+            last_used_at: None,
+            use_count: 0,
+            origin,
         };
-        let link: Option<&Link> = self
-            .database
-            .library
-            .links
-            .iter()
-            .find(|link| link.title.to_lowercase() == title.to_lowercase());
-        if link.is_some() {
-            return "Error: Link with that title already exists".to_string();
-        }
-        self.database.library.links.push(link_entry);
-        if let Err(e) = self.database.save() {
-            return format!("Error: Could not save to database cause: {}", e);
-        }
-        "Success: Added link".to_string()
+        self.backup(origin)?;
+        let index = links.len();
+        self.store_mut(origin)?.insert_link(link_entry.clone())?;
+        self.record(
+            format!("add link '{}'", link_entry.title),
+            Change::Link {
+                index,
+                before: None,
+                after: Some(link_entry.clone()),
+            },
+            origin,
+        )?;
+        Ok(link_entry.id)
     }
 
+    /// Adds a command topic to the library of `origin` and returns its id.
     pub fn add(
         &mut self,
         title: String,
         description: String,
         cmd: String,
         category: String,
-    ) -> String {
+        details: TopicDetails,
+        origin: Origin,
+    ) -> Result<String, TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let topics = &self.store(origin)?.library().topics;
+        if topics
+            .iter()
+            .any(|tpc| tpc.title.to_lowercase() == title.to_lowercase())
+        {
+            return Err(TermnoteError::Duplicate(
+                "Command topic with that title already exists".to_string(),
+            ));
+        }
+        let all_topics = self.topics();
+        let now = Utc::now();
         let topic_entry = Topic {
-            title: title.clone(),
+            id: generate_id(|id| all_topics.iter().any(|tpc| tpc.id == id)),
+            title,
             description,
             command: cmd,
             category,
+            tags: normalize_tags(&details.tags.unwrap_or_default()),
+            steps: details.steps.unwrap_or_default(),
+            cwd: details.cwd.flatten(),
+            env: details.env.unwrap_or_default(),
+            shell: details.shell.flatten(),
+            dangerous: details.dangerous.unwrap_or_default(),
+            created_at: now,
+            updated_at: now,
+            last_used_at: None,
+            use_count: 0,
+            origin,
         };
-        let topic: Option<&Topic> = self
-            .database
-            .library
-            .topics
-            .iter()
-            .find(|tpc| tpc.title.to_lowercase() == title.to_lowercase());
-        if topic.is_some() {
-            return "Error: Command topic with that title already exists".to_string();
-        }
-        self.database.library.topics.push(topic_entry);
-        if let Err(e) = self.database.save() {
-            return format!("Error: Could not save to database cause: {}", e);
-        }
-        "Success: Added topic".to_string()
+        self.backup(origin)?;
+        let index = topics.len();
+        self.store_mut(origin)?.insert_topic(topic_entry.clone())?;
+        self.record(
+            format!("add topic '{}'", topic_entry.title),
+            Change::Topic {
+                index,
+                before: None,
+                after: Some(topic_entry.clone()),
+            },
+            origin,
+        )?;
+        Ok(topic_entry.id)
     }
 
     pub fn update(
         &mut self,
-        index: usize,
+        key: &str,
         title: Option<&String>,
         description: Option<&String>,
         cmd: Option<&String>,
         category: Option<&String>,
-    ) -> String {
-        if index >= self.database.library.topics.len() {
-            return "Error: Could not find topic".to_string();
+        details: TopicDetails,
+    ) -> Result<(), TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let before = self.find_topic(key)?;
+
+        if title.is_none()
+            && description.is_none()
+            && cmd.is_none()
+            && category.is_none()
+            && details.is_empty()
+        {
+            return Err(TermnoteError::Validation("No fields to update".to_string()));
         }
 
-        if title.is_none() && description.is_none() && cmd.is_none() && category.is_none() {
-            return "Error: No fields to update".to_string();
+        if let Some(new_title) = title {
+            let topics = &self.store(before.origin)?.library().topics;
+            if topics.iter().any(|tpc| {
+                tpc.id != before.id && tpc.title.to_lowercase() == new_title.to_lowercase()
+            }) {
+                return Err(TermnoteError::Duplicate(
+                    "Command topic with that title already exists".to_string(),
+                ));
+            }
         }
 
-        let topic = &mut self.database.library.topics[index];
+        let mut topic = before.clone();
         if let Some(new_title) = title {
             topic.title = new_title.to_string();
         }
@@ -95,125 +467,1270 @@ impl CRUDProcessor {
         if let Some(new_category) = category {
             topic.category = new_category.to_string();
         }
-        if self.database.save().is_err() {
-            return "Error: Could not update the database".to_string();
+        if let Some(new_tags) = details.tags {
+            topic.tags = normalize_tags(&new_tags);
+        }
+        if let Some(new_steps) = details.steps {
+            topic.steps = new_steps;
+        }
+        if let Some(new_cwd) = details.cwd {
+            topic.cwd = new_cwd;
+        }
+        if let Some(new_env) = details.env {
+            topic.env = new_env;
         }
-        "Success: Updated topic".to_string()
+        if let Some(new_shell) = details.shell {
+            topic.shell = new_shell;
+        }
+        if let Some(dangerous) = details.dangerous {
+            topic.dangerous = dangerous;
+        }
+        topic.updated_at = Utc::now();
+        let origin = before.origin;
+        self.backup(origin)?;
+        self.store_mut(origin)?
+            .update_topic(&before.id, topic.clone())?;
+        self.record(
+            format!("update topic '{}'", before.title),
+            Change::Topic {
+                index: self.topic_position(origin, &before.id)?,
+                before: Some(before),
+                after: Some(topic),
+            },
+            origin,
+        )
     }
 
     pub fn update_url(
         &mut self,
-        index: usize,
+        key: &str,
         title: Option<&String>,
         url: Option<&String>,
-    ) -> String {
-        if index >= self.database.library.links.len() {
-            return "Error: Could not find link".to_string();
+        tags: Option<Vec<String>>,
+    ) -> Result<(), TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let before = self.find_link(key)?;
+
+        if title.is_none() && url.is_none() && tags.is_none() {
+            return Err(TermnoteError::Validation("No fields to update".to_string()));
         }
 
-        if title.is_none() && url.is_none() {
-            return "Error: No fields to update".to_string();
+        if let Some(new_title) = title {
+            let links = &self.store(before.origin)?.library().links;
+            if links.iter().any(|link| {
+                link.id != before.id && link.title.to_lowercase() == new_title.to_lowercase()
+            }) {
+                return Err(TermnoteError::Duplicate(
+                    "Link with that title already exists".to_string(),
+                ));
+            }
         }
 
-        let link = &mut self.database.library.links[index];
+        let mut link = before.clone();
         if let Some(new_title) = title {
             link.title = new_title.to_string();
         }
         if let Some(new_url) = url {
             link.url = new_url.to_string();
         }
-        if self.database.save().is_err() {
-            return "Error: Could not update the database".to_string();
+        if let Some(new_tags) = tags {
+            link.tags = normalize_tags(&new_tags);
         }
-        "Success: Updated link".to_string()
+        link.updated_at = Utc::now();
+        let origin = before.origin;
+        self.backup(origin)?;
+        self.store_mut(origin)?
+            .update_link(&before.id, link.clone())?;
+        self.record(
+            format!("update link '{}'", before.title),
+            Change::Link {
+                index: self.link_position(origin, &before.id)?,
+                before: Some(before),
+                after: Some(link),
+            },
+            origin,
+        )
     }
 
-    pub fn remove_by_index(&mut self, index: usize) -> String {
-        if index >= self.database.library.topics.len() {
-            return "Error: Could not find topic".to_string();
+    pub fn remove(&mut self, key: &str) -> Result<(), TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let found = self.find_topic(key)?;
+        let origin = found.origin;
+        let index = self.topic_position(origin, &found.id)?;
+        self.backup(origin)?;
+        let topic = self.store_mut(origin)?.delete_topic(&found.id)?;
+        self.record(
+            format!("remove topic '{}'", topic.title),
+            Change::Topic {
+                index,
+                before: Some(topic),
+                after: None,
+            },
+            origin,
+        )
+    }
+
+    /// Searches all libraries in lookup order.
+    pub fn search_by_title_description_category(
+        &self,
+        query: &str,
+    ) -> Result<Vec<Topic>, TermnoteError> {
+        let mut results = Vec::new();
+        for (origin, store) in self.layers() {
+            results.extend(
+                store
+                    .search_topics(query)?
+                    .into_iter()
+                    .map(|topic| Topic { origin, ..topic }),
+            );
         }
-        self.database.library.topics.remove(index);
-        if self.database.save().is_err() {
-            return "Error: Could not update the database".to_string();
+        Ok(results)
+    }
+
+    pub fn remove_url(&mut self, key: &str) -> Result<(), TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let found = self.find_link(key)?;
+        let origin = found.origin;
+        let index = self.link_position(origin, &found.id)?;
+        self.backup(origin)?;
+        let link = self.store_mut(origin)?.delete_link(&found.id)?;
+        self.record(
+            format!("remove link '{}'", link.title),
+            Change::Link {
+                index,
+                before: Some(link),
+                after: None,
+            },
+            origin,
+        )
+    }
+
+    /// Searches the links of all libraries in lookup order.
+    pub fn search_links_by_title(&self, query: &str) -> Result<Vec<Link>, TermnoteError> {
+        let mut results = Vec::new();
+        for (origin, store) in self.layers() {
+            results.extend(
+                store
+                    .search_links(query)?
+                    .into_iter()
+                    .map(|link| Link { origin, ..link }),
+            );
         }
-        "Success: Removed topic".to_string()
+        Ok(results)
     }
 
-    pub fn search_by_title_description_category(&self, query: &str) -> Vec<(u16, &Topic)> {
-        self.database
-            .library
-            .topics
-            .iter()
-            .enumerate()
-            .filter(|(_, topic)| {
-                topic.title.to_lowercase().contains(&query.to_lowercase())
-                    || topic
-                        .description
-                        .to_lowercase()
-                        .contains(&query.to_lowercase())
-                    || topic
-                        .category
-                        .to_lowercase()
-                        .contains(&query.to_lowercase())
+    /// Copies a shared topic into the library of `origin` so that it can be
+    /// edited, returning the id of the copy.
+    pub fn fork(&mut self, key: &str, origin: Origin) -> Result<String, TermnoteError> {
+        let topic = self.find_shared_topic(key)?;
+        self.add(
+            topic.title,
+            topic.description,
+            topic.command,
+            topic.category,
+            TopicDetails {
+                tags: Some(topic.tags),
+                steps: Some(topic.steps),
+                cwd: Some(topic.cwd),
+                env: Some(topic.env),
+                shell: Some(topic.shell),
+                dangerous: Some(topic.dangerous),
+            },
+            origin,
+        )
+    }
+
+    /// Copies a shared link into the library of `origin`, returning the id of the copy.
+    pub fn fork_url(&mut self, key: &str, origin: Origin) -> Result<String, TermnoteError> {
+        let link = self.find_shared_link(key)?;
+        self.add_url(link.title, link.url, link.tags, origin)
+    }
+
+    fn find_shared_topic(&self, key: &str) -> Result<Topic, TermnoteError> {
+        let found = self.find_topic(key)?;
+        if found.origin == Origin::Shared {
+            return Ok(found);
+        }
+        // a personal topic may shadow the shared one by title
+        self.topics()
+            .into_iter()
+            .find(|topic| {
+                topic.origin == Origin::Shared && topic.title.to_lowercase() == key.to_lowercase()
             })
-            .map(|(index, topic)| (index as u16, topic))
-            .collect()
+            .ok_or_else(|| {
+                TermnoteError::Validation(format!("Topic {} is not from a shared library", key))
+            })
+    }
+
+    fn find_shared_link(&self, key: &str) -> Result<Link, TermnoteError> {
+        let found = self.find_link(key)?;
+        if found.origin == Origin::Shared {
+            return Ok(found);
+        }
+        self.links()
+            .into_iter()
+            .find(|link| {
+                link.origin == Origin::Shared && link.title.to_lowercase() == key.to_lowercase()
+            })
+            .ok_or_else(|| {
+                TermnoteError::Validation(format!("Link {} is not from a shared library", key))
+            })
+    }
+
+    /// Counts a use of the topic, i.e. its command was executed or returned.
+    /// Usage statistics are neither backed up nor journaled and not kept for
+    /// entries of shared libraries.
+    pub fn record_topic_use(&mut self, id: &str) -> Result<(), TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let mut topic = self.find_topic(id)?;
+        if topic.origin == Origin::Shared {
+            return Ok(());
+        }
+        topic.last_used_at = Some(Utc::now());
+        topic.use_count += 1;
+        self.store_mut(topic.origin)?.update_topic(id, topic)
+    }
+
+    /// Counts a use of the link, i.e. its URL was returned.
+    pub fn record_link_use(&mut self, id: &str) -> Result<(), TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let mut link = self.find_link(id)?;
+        if link.origin == Origin::Shared {
+            return Ok(());
+        }
+        link.last_used_at = Some(Utc::now());
+        link.use_count += 1;
+        self.store_mut(link.origin)?.update_link(id, link)
+    }
+
+    /// Runs a command of the topic with the ID and title and records the
+    /// execution in the history. A history which cannot be written only gives
+    /// a warning, as the command ran nonetheless.
+    pub fn execute(
+        &self,
+        topic_id: &str,
+        title: &str,
+        command: &str,
+        context: &ExecContext,
+    ) -> Result<Outcome, TermnoteError> {
+        let started_at = Utc::now();
+        let outcome = run_cmd(command, context)?;
+        if let Some(executions) = &self.executions {
+            let execution = Execution {
+                number: 0,
+                topic_id: topic_id.to_string(),
+                title: title.to_string(),
+                command: command.to_string(),
+                cwd: context.resolved_cwd(),
+                shell: context.shell,
+                env: context.env.clone(),
+                started_at,
+                ended_at: Utc::now(),
+                exit_code: outcome.exit_code(),
+                timed_out: outcome.timed_out,
+                output: outcome.output.clone(),
+                output_truncated: outcome.output_truncated,
+            };
+            if let Err(e) = executions.record(execution) {
+                display_warning(&format!(
+                    "The execution was not recorded in the history: {}",
+                    e
+                ));
+            }
+        }
+        Ok(outcome)
+    }
+
+    fn execution_log(&self) -> Result<&ExecutionLog, TermnoteError> {
+        self.executions.as_ref().ok_or_else(|| {
+            TermnoteError::Config("The execution history is not available".to_string())
+        })
+    }
+
+    /// Lists the recorded executions, newest first.
+    pub fn executions(&self) -> Result<Vec<Execution>, TermnoteError> {
+        let mut executions = self.execution_log()?.read()?;
+        executions.reverse();
+        Ok(executions)
+    }
+
+    pub fn find_execution(&self, number: u64) -> Result<Execution, TermnoteError> {
+        self.execution_log()?.find(number)
+    }
+
+    fn backup_store(&self) -> Result<&BackupStore, TermnoteError> {
+        self.backups
+            .as_ref()
+            .ok_or_else(|| TermnoteError::Config("Backups are not available".to_string()))
+    }
+
+    pub fn list_backups(&self) -> Result<Vec<Backup>, TermnoteError> {
+        self.backup_store()?.list()
+    }
+
+    /// Changes to the current library which restoring the backup would apply.
+    pub fn backup_diff(&self, id: &str) -> Result<LibraryDiff, TermnoteError> {
+        let snapshot = self.backup_store()?.read(id)?;
+        Ok(LibraryDiff::between(self.database.library(), &snapshot))
+    }
+
+    pub fn restore_backup(&mut self, id: &str) -> Result<(), TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let snapshot = self.backup_store()?.read(id)?;
+        self.backup(Origin::Global)?;
+        self.database.replace_library(snapshot)?;
+        // the recorded operations no longer match the restored library
+        if let Some(journal) = &self.journal {
+            journal.clear()?;
+        }
+        self.commit(&format!("restore backup {}", id))
+    }
+
+    /// Checks the global library for problems, see [`diagnose`].
+    pub fn diagnose(&self) -> Diagnosis {
+        diagnose(self.database.library())
+    }
+
+    /// Applies every fix `tn doctor` offers, returning how many were applied.
+    pub fn repair(&mut self) -> Result<usize, TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let diagnosis = self.diagnose();
+        let fixes = diagnosis.fixable();
+        if fixes == 0 {
+            return Ok(0);
+        }
+        self.backup(Origin::Global)?;
+        self.database.replace_library(diagnosis.fixed)?;
+        // the recorded operations refer to entries which may have changed
+        if let Some(journal) = &self.journal {
+            journal.clear()?;
+        }
+        self.commit("fix library problems")?;
+        Ok(fixes)
+    }
+
+    fn journal(&self) -> Result<&Journal, TermnoteError> {
+        self.journal
+            .as_ref()
+            .ok_or_else(|| TermnoteError::Config("The journal is not available".to_string()))
+    }
+
+    /// Locks and loads the library a journal entry was recorded in, which
+    /// may be the project library of another directory.
+    fn entry_library(
+        &mut self,
+        entry: &JournalEntry,
+    ) -> Result<(Origin, Option<FileLock>), TermnoteError> {
+        let Some(path) = &entry.library else {
+            return Ok((Origin::Global, None));
+        };
+        if self
+            .local
+            .as_ref()
+            .is_some_and(|local| local.path() == path)
+        {
+            return Ok((Origin::Local, None));
+        }
+        if !path.exists() {
+            return Err(TermnoteError::NotFound(format!(
+                "The project library {} no longer exists",
+                path.display()
+            )));
+        }
+        let lock = FileLock::acquire(&sibling_path(path, ".lock"))?;
+        self.local = Some(ObjectDB::init(path)?);
+        Ok((Origin::Local, Some(lock)))
+    }
+
+    /// Reverts the latest recorded operation, returning its description.
+    pub fn undo(&mut self) -> Result<String, TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let mut state = self.journal()?.read()?;
+        let entry = state
+            .undo
+            .pop()
+            .ok_or_else(|| TermnoteError::NotFound("Nothing to undo".to_string()))?;
+        let (origin, _lock) = self.entry_library(&entry)?;
+        self.backup(origin)?;
+        self.apply_change(&entry.change, true, origin)?;
+        let description = entry.description.clone();
+        state.redo.push(entry);
+        self.journal()?.write(&state)?;
+        if origin == Origin::Global {
+            self.commit(&format!("undo {}", description))?;
+        }
+        Ok(description)
+    }
+
+    /// Applies the latest undone operation again, returning its description.
+    pub fn redo(&mut self) -> Result<String, TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let mut state = self.journal()?.read()?;
+        let entry = state
+            .redo
+            .pop()
+            .ok_or_else(|| TermnoteError::NotFound("Nothing to redo".to_string()))?;
+        let (origin, _lock) = self.entry_library(&entry)?;
+        self.backup(origin)?;
+        self.apply_change(&entry.change, false, origin)?;
+        let description = entry.description.clone();
+        state.undo.push(entry);
+        self.journal()?.write(&state)?;
+        if origin == Origin::Global {
+            self.commit(&format!("redo {}", description))?;
+        }
+        Ok(description)
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Encrypts the global library, its backups, its journal and its execution
+    /// history with `cipher`, or stores them in plaintext again for `None`.
+    pub fn set_cipher(&mut self, cipher: Option<Cipher>) -> Result<(), TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        self.database.set_cipher(cipher.clone())?;
+        if let Some(backups) = &mut self.backups {
+            backups.set_cipher(cipher.clone())?;
+        }
+        if let Some(journal) = &mut self.journal {
+            journal.set_cipher(cipher.clone())?;
+        }
+        if let Some(executions) = &mut self.executions {
+            executions.set_cipher(cipher.clone())?;
+        }
+        let message = match cipher {
+            Some(_) => "encrypt the library",
+            None => "decrypt the library",
+        };
+        self.cipher = cipher;
+        self.commit(message)
+    }
+
+    fn git_sync(&self) -> Result<&GitSync, TermnoteError> {
+        self.sync.as_ref().ok_or_else(|| {
+            TermnoteError::Config(
+                "Sync needs the JSON library inside the termnote home directory".to_string(),
+            )
+        })
+    }
+
+    fn initialized_sync(&self) -> Result<&GitSync, TermnoteError> {
+        let sync = self.git_sync()?;
+        if !sync.is_initialized() {
+            return Err(TermnoteError::Sync(
+                "The library is not synced yet, run `tn sync init` first".to_string(),
+            ));
+        }
+        Ok(sync)
+    }
+
+    /// Starts syncing the termnote home directory through git.
+    pub fn sync_init(&mut self, remote: Option<&str>) -> Result<(), TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        self.git_sync()?.init(remote)
+    }
+
+    /// Short git status of the synced home directory, including how far it is
+    /// ahead of or behind the remote.
+    pub fn sync_status(&self) -> Result<String, TermnoteError> {
+        self.initialized_sync()?
+            .git(&["status", "--short", "--branch"])
+    }
+
+    /// Pushes the committed library changes to the remote.
+    pub fn sync_push(&mut self) -> Result<(), TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let sync = self.initialized_sync()?;
+        // usage statistics are not committed when they change
+        sync.commit("update usage statistics")?;
+        match sync.upstream()? {
+            Some(_) => sync.git(&["push", "--quiet"])?,
+            None => sync.git(&["push", "--quiet", "--set-upstream", "origin", "HEAD"])?,
+        };
+        Ok(())
+    }
+
+    /// Pulls the remote library. Diverged libraries are merged entry by entry
+    /// instead of textually, see [`merge_libraries`]. Returns a summary.
+    pub fn sync_pull(&mut self) -> Result<String, TermnoteError> {
+        let _locks = self.lock_and_reload()?;
+        let sync = self.initialized_sync()?;
+        sync.commit("update usage statistics")?;
+        sync.git(&["fetch", "--quiet"])?;
+        let upstream = sync.tracking_branch()?.ok_or_else(|| {
+            TermnoteError::Sync("No remote branch to pull from, push first".to_string())
+        })?;
+        if sync.is_ancestor(&upstream, "HEAD")? {
+            return Ok("Already up to date".to_string());
+        }
+        if sync.is_ancestor("HEAD", &upstream)? {
+            sync.git(&["merge", "--quiet", "--ff-only", &upstream])?;
+            self.database.load()?;
+            return Ok(format!("Fast-forwarded to {}", upstream));
+        }
+
+        let base_revision = sync.merge_base("HEAD", &upstream)?;
+        let library_file = sync.library_file().to_path_buf();
+        let read_revision = |revision: &str| -> Result<Library, TermnoteError> {
+            match sync.show_library(revision)? {
+                // revisions from before the library was encrypted are plaintext,
+                // the merged library is encrypted again when it is saved
+                Some(contents) => match &self.cipher {
+                    Some(cipher) if is_encrypted(&contents) => {
+                        ObjectDB::decode_library(&cipher.open(&contents)?, &library_file)
+                    }
+                    _ => ObjectDB::decode_library(&contents, &library_file),
+                },
+                None => Ok(Library::default()),
+            }
+        };
+        let base = match &base_revision {
+            Some(revision) => read_revision(revision)?,
+            None => Library::default(),
+        };
+        let theirs = read_revision(&upstream)?;
+        let merged = merge_libraries(&base, self.database.library(), &theirs);
+
+        let mut merge_args = vec!["merge", "--quiet", "--no-commit", "--no-ff"];
+        if base_revision.is_none() {
+            merge_args.push("--allow-unrelated-histories");
+        }
+        merge_args.push(&upstream);
+        // a textual conflict in the library file is expected and resolved below
+        let merge_result = sync.git(&merge_args);
+        if !sync.is_merging()? {
+            merge_result?;
+        }
+        self.database.replace_library(merged)?;
+        let sync = self.initialized_sync()?;
+        sync.git(&["add", "--", &library_file.to_string_lossy()])?;
+        let conflicts = sync.conflicted_files()?;
+        if !conflicts.is_empty() {
+            sync.git(&["merge", "--abort"])?;
+            self.database.load()?;
+            return Err(TermnoteError::Sync(format!(
+                "Could not merge {}, resolve the conflicts with git",
+                conflicts.join(", ")
+            )));
+        }
+        sync.git(&["commit", "--quiet", "--no-edit"])?;
+        Ok(format!("Merged {} into the library", upstream))
     }
 
-    pub fn remove_url_by_index(&mut self, index: usize) -> String {
-        if index >= self.database.library.links.len() {
-            return "Error: Could not find link".to_string();
+    fn apply_change(
+        &mut self,
+        change: &Change,
+        revert: bool,
+        origin: Origin,
+    ) -> Result<(), TermnoteError> {
+        let mut library = self.store(origin)?.library().clone();
+        match change {
+            Change::Topic {
+                index,
+                before,
+                after,
+            } => {
+                let (expected, target) = if revert {
+                    (after, before)
+                } else {
+                    (before, after)
+                };
+                apply_entry_change(&mut library.topics, *index, expected, target, |topic| {
+                    &topic.id
+                })?;
+            }
+            Change::Link {
+                index,
+                before,
+                after,
+            } => {
+                let (expected, target) = if revert {
+                    (after, before)
+                } else {
+                    (before, after)
+                };
+                apply_entry_change(&mut library.links, *index, expected, target, |link| {
+                    &link.id
+                })?;
+            }
+        }
+        self.store_mut(origin)?.replace_library(library)
+    }
+}
+
+fn read_only() -> TermnoteError {
+    TermnoteError::Validation(
+        "Entries of shared libraries are read-only, copy them with `tn fork` to make changes"
+            .to_string(),
+    )
+}
+
+fn missing_local() -> TermnoteError {
+    TermnoteError::NotFound(format!(
+        "No project library ({}) found in this directory or above",
+        LOCAL_FILE
+    ))
+}
+
+/// Minimum age of the newest backup before another one is taken. A SQLite database is
+/// written entry by entry, snapshotting it as a whole on every change would undo that.
+fn backup_interval(backend: Backend) -> TimeDelta {
+    match backend {
+        Backend::Json => TimeDelta::zero(),
+        Backend::Sqlite => TimeDelta::minutes(10),
+    }
+}
+
+/// Moves the entry from the `expected` to the `target` state, refusing if the
+/// library was changed in between by something not journaled. Entries are
+/// matched by id, `index` is the position to restore removed entries at.
+fn apply_entry_change<T: Clone>(
+    entries: &mut Vec<T>,
+    index: usize,
+    expected: &Option<T>,
+    target: &Option<T>,
+    id_of: impl Fn(&T) -> &String,
+) -> Result<(), TermnoteError> {
+    let changed_outside = || {
+        TermnoteError::Validation(
+            "The library was changed outside of termnote since this operation".to_string(),
+        )
+    };
+    match (expected, target) {
+        (Some(expected_entry), target) => {
+            let position = entries
+                .iter()
+                .position(|entry| id_of(entry) == id_of(expected_entry))
+                .ok_or_else(changed_outside)?;
+            match target {
+                Some(entry) => entries[position] = entry.clone(),
+                None => {
+                    entries.remove(position);
+                }
+            }
+        }
+        (None, Some(entry)) => {
+            if entries.iter().any(|other| id_of(other) == id_of(entry)) {
+                return Err(changed_outside());
+            }
+            entries.insert(index.min(entries.len()), entry.clone());
         }
-        self.database.library.links.remove(index);
-        if self.database.save().is_err() {
-            return "Error: Could not update the database".to_string();
+        (None, None) => {}
+    }
+    Ok(())
+}
+
+/// Orders in which topics and links can be listed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Order in which the entries were added.
+    Added,
+    /// Most recently used first.
+    Recent,
+    /// Most often used first.
+    MostUsed,
+}
+
+impl SortOrder {
+    pub fn from_name(name: &str) -> Result<Self, TermnoteError> {
+        match name {
+            "added" => Ok(SortOrder::Added),
+            "recent" => Ok(SortOrder::Recent),
+            "used" => Ok(SortOrder::MostUsed),
+            other => Err(TermnoteError::Validation(format!(
+                "Unknown sort order '{}'",
+                other
+            ))),
         }
-        "Success: Removed link".to_string()
     }
+}
+
+pub fn sort_topics(topics: &mut [Topic], order: SortOrder) {
+    match order {
+        SortOrder::Added => {}
+        SortOrder::Recent => topics.sort_by_key(|topic| Reverse(topic.last_used_at)),
+        SortOrder::MostUsed => topics.sort_by_key(|topic| Reverse(topic.use_count)),
+    }
+}
+
+pub fn sort_links(links: &mut [Link], order: SortOrder) {
+    match order {
+        SortOrder::Added => {}
+        SortOrder::Recent => links.sort_by_key(|link| Reverse(link.last_used_at)),
+        SortOrder::MostUsed => links.sort_by_key(|link| Reverse(link.use_count)),
+    }
+}
+
+pub fn deserialize_backups(backups: &[Backup]) -> String {
+    let mut backups_output: String = "".to_string();
+    for backup in backups.iter() {
+        backups_output.push_str(
+            format!(
+                "{} - {} \n",
+                backup.id.bright_green(),
+                backup.created_at.format("%Y-%m-%d %H:%M:%S")
+            )
+            .as_str(),
+        );
+    }
+    backups_output
+}
+
+fn execution_status(execution: &Execution) -> String {
+    if execution.success() {
+        "ok".green().to_string()
+    } else if execution.timed_out {
+        "timed out".red().to_string()
+    } else {
+        format!("exit code {}", execution.exit_code)
+            .red()
+            .to_string()
+    }
+}
+
+pub fn deserialize_executions(executions: &[Execution]) -> String {
+    let mut executions_output: String = "".to_string();
+    for execution in executions.iter() {
+        let duration = execution.ended_at - execution.started_at;
+        executions_output.push_str(&format!(
+            "{} - {} {}: {} - {} after {:.1}s in {} \n    {} \n",
+            execution.number.to_string().bright_green(),
+            execution
+                .started_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            execution.topic_id,
+            execution.title,
+            execution_status(execution),
+            duration.num_milliseconds() as f64 / 1000.0,
+            execution.cwd.display(),
+            execution.command.bright_green()
+        ));
+    }
+    executions_output
+}
 
-    pub fn search_links_by_title(&self, query: &str) -> Vec<(u16, &Link)> {
-        self.database
-            .library
-            .links
+/// All recorded details of an execution including its captured output.
+pub fn deserialize_execution(execution: &Execution) -> String {
+    let mut lines = vec![
+        format!(
+            "Execution {} of {}: {}",
+            execution.number, execution.topic_id, execution.title
+        ),
+        format!("$ {}", execution.command.bright_green()),
+        format!("cwd: {}", execution.cwd.display()),
+    ];
+    if let Some(shell) = execution.shell {
+        lines.push(format!("shell: {}", shell.name()));
+    }
+    lines.extend(
+        execution
+            .env
             .iter()
-            .enumerate()
-            .filter(|(_, link)| link.title.to_lowercase().contains(&query.to_lowercase()))
-            .map(|(index, link)| (index as u16, link))
-            .collect()
+            .map(|(name, value)| format!("env: {}={}", name, value)),
+    );
+    for (label, time) in [
+        ("started", execution.started_at),
+        ("ended", execution.ended_at),
+    ] {
+        lines.push(format!(
+            "{}: {}",
+            label,
+            time.with_timezone(&Local).format("%Y-%m-%d %H:%M:%S%.3f")
+        ));
+    }
+    lines.push(format!("status: {}", execution_status(execution)));
+    match &execution.output {
+        Some(output) if execution.output_truncated => {
+            lines.push("output (truncated to the end):".to_string());
+            lines.push(output.trim_end().to_string());
+        }
+        Some(output) => {
+            lines.push("output:".to_string());
+            lines.push(output.trim_end().to_string());
+        }
+        None => lines.push("output: not captured".to_string()),
     }
+    lines.join("\n")
 }
 
-pub fn run_cmd(cmd_str: &str) -> String {
-    let mut command = shell(cmd_str);
-    command.stdout(Stdio::piped());
-    let output_rs = command.execute_output();
-    if let Err(e) = output_rs {
-        return format!("{}", e);
+/// Marker appended to the title of entries which are not from the global library.
+fn origin_marker(origin: Origin) -> String {
+    match origin {
+        Origin::Global => "".to_string(),
+        other => format!(" ({})", other.name()).cyan().to_string(),
     }
-    String::from_utf8(output_rs.unwrap().stdout).unwrap()
 }
 
-pub fn deserialize_links(links: &Vec<(u16, &Link)>) -> String {
+fn tags_marker(tags: &[String]) -> String {
+    if tags.is_empty() {
+        return "".to_string();
+    }
+    format!(" [{}]", tags.join(", ")).magenta().to_string()
+}
+
+pub fn deserialize_links(links: &[Link]) -> String {
     let mut links_output: String = "".to_string();
-    for (index, link) in links.iter() {
-        links_output
-            .push_str(format!("{}: {} - {} \n", index, link.title, link.url.yellow()).as_str());
+    for link in links.iter() {
+        links_output.push_str(
+            format!(
+                "{}: {}{}{} - {} \n",
+                link.id,
+                link.title,
+                origin_marker(link.origin),
+                tags_marker(&link.tags),
+                link.url.yellow()
+            )
+            .as_str(),
+        );
     }
     links_output
 }
 
-pub fn deserialize_topics(topics: &Vec<(u16, &Topic)>) -> String {
+pub fn deserialize_topics(topics: &[Topic]) -> String {
     let mut topics_output: String = "".to_string();
-    for (index, topic) in topics.iter() {
+    for topic in topics.iter() {
         topics_output.push_str(
             format!(
-                "{}: {} - {} \n",
-                index,
+                "{}: {}{}{} - {} \n",
+                topic.id,
                 topic.title,
+                origin_marker(topic.origin),
+                tags_marker(&topic.tags),
                 topic.command.bright_green()
             )
             .as_str(),
         );
+        for (index, step) in topic.steps.iter().enumerate() {
+            let description = if step.description.is_empty() {
+                String::new()
+            } else {
+                format!("{} - ", step.description)
+            };
+            topics_output.push_str(&format!(
+                "    {}. {}{} \n",
+                index + 1,
+                description,
+                step.command.bright_green()
+            ));
+        }
     }
     topics_output
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Library, MemoryDB};
+
+    fn processor() -> CRUDProcessor {
+        CRUDProcessor::with_repository(Box::new(MemoryDB::default()))
+    }
+
+    fn add(
+        processor: &mut CRUDProcessor,
+        title: &str,
+        category: &str,
+    ) -> Result<String, TermnoteError> {
+        processor.add(
+            title.to_string(),
+            format!("{} description", title),
+            "ls".to_string(),
+            category.to_string(),
+            TopicDetails::default(),
+            Origin::Global,
+        )
+    }
+
+    /// Processor with a shared library holding the given topics.
+    fn with_shared(titles: &[(&str, &str)]) -> CRUDProcessor {
+        let mut team = processor();
+        for (title, category) in titles {
+            add(&mut team, title, category).unwrap();
+        }
+        let library = Library {
+            topics: team.topics(),
+            ..Library::default()
+        };
+        let mut processor = processor();
+        processor.shared.push(MemoryDB::new(library));
+        processor
+    }
+
+    #[test]
+    fn adds_updates_and_removes_topics() {
+        let mut processor = processor();
+        let id = add(&mut processor, "List", "files").unwrap();
+        assert_eq!(processor.find_topic("list").unwrap().id, id);
+        assert!(matches!(
+            add(&mut processor, "list", "other"),
+            Err(TermnoteError::Duplicate(_))
+        ));
+
+        let command = "ls -la".to_string();
+        processor
+            .update(
+                &id,
+                None,
+                None,
+                Some(&command),
+                None,
+                TopicDetails::default(),
+            )
+            .unwrap();
+        assert_eq!(processor.find_topic(&id).unwrap().command, "ls -la");
+        assert!(matches!(
+            processor.update(&id, None, None, None, None, TopicDetails::default()),
+            Err(TermnoteError::Validation(_))
+        ));
+        assert!(matches!(
+            processor.update(
+                "missing",
+                None,
+                None,
+                Some(&command),
+                None,
+                TopicDetails::default()
+            ),
+            Err(TermnoteError::NotFound(_))
+        ));
+
+        add(&mut processor, "Ping", "network").unwrap();
+        let renamed = "PING".to_string();
+        assert!(matches!(
+            processor.update(
+                &id,
+                Some(&renamed),
+                None,
+                None,
+                None,
+                TopicDetails::default()
+            ),
+            Err(TermnoteError::Duplicate(_))
+        ));
+        let renamed = "list".to_string();
+        processor
+            .update(
+                &id,
+                Some(&renamed),
+                None,
+                None,
+                None,
+                TopicDetails::default(),
+            )
+            .unwrap();
+        assert_eq!(processor.find_topic(&id).unwrap().title, "list");
+        processor.remove("Ping").unwrap();
+
+        processor.remove("List").unwrap();
+        assert!(processor.database.library().topics.is_empty());
+        assert!(matches!(
+            processor.remove(&id),
+            Err(TermnoteError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn ids_stay_with_their_entries() {
+        let mut processor = processor();
+        let first = add(&mut processor, "First", "misc").unwrap();
+        let second = add(&mut processor, "Second", "misc").unwrap();
+        assert_ne!(first, second);
+        processor.remove(&first).unwrap();
+        assert_eq!(processor.find_topic(&second).unwrap().title, "Second");
+    }
+
+    #[test]
+    fn undoes_and_redoes_operations() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut processor = processor();
+        processor.journal = Some(Journal::new(&dir.path().join("db.journal.json"), 10));
+        let id = add(&mut processor, "List", "files").unwrap();
+        let command = "ls -la".to_string();
+        processor
+            .update(
+                &id,
+                None,
+                None,
+                Some(&command),
+                None,
+                TopicDetails::default(),
+            )
+            .unwrap();
+        let commands = |processor: &CRUDProcessor| -> Vec<String> {
+            let topics = &processor.database.library().topics;
+            topics.iter().map(|topic| topic.command.clone()).collect()
+        };
+
+        assert_eq!(processor.undo().unwrap(), "update topic 'List'");
+        assert_eq!(commands(&processor), ["ls"]);
+        processor.undo().unwrap();
+        assert!(commands(&processor).is_empty());
+        assert!(matches!(processor.undo(), Err(TermnoteError::NotFound(_))));
+        processor.redo().unwrap();
+        processor.redo().unwrap();
+        assert_eq!(commands(&processor), ["ls -la"]);
+        assert!(matches!(processor.redo(), Err(TermnoteError::NotFound(_))));
+
+        // a removal which was not journaled keeps the operation from being undone
+        processor.database.library_mut().topics.clear();
+        assert!(matches!(
+            processor.undo(),
+            Err(TermnoteError::Validation(_))
+        ));
+        assert!(commands(&processor).is_empty());
+    }
+
+    fn entries(ids: &[&str]) -> Vec<(String, u32)> {
+        ids.iter().map(|id| (id.to_string(), 0)).collect()
+    }
+
+    fn ids(entries: &[(String, u32)]) -> Vec<&str> {
+        entries.iter().map(|(id, _)| id.as_str()).collect()
+    }
+
+    fn id_of(entry: &(String, u32)) -> &String {
+        &entry.0
+    }
+
+    #[test]
+    fn entry_changes_are_applied_by_id() {
+        let mut list = entries(&["a", "b", "c"]);
+        let b = Some(("b".to_string(), 0));
+        let changed_b = Some(("b".to_string(), 1));
+        apply_entry_change(&mut list, 1, &b, &changed_b, id_of).unwrap();
+        assert_eq!(list[1], ("b".to_string(), 1));
+        apply_entry_change(&mut list, 1, &changed_b, &None, id_of).unwrap();
+        assert_eq!(ids(&list), ["a", "c"]);
+        apply_entry_change(&mut list, 1, &None, &changed_b, id_of).unwrap();
+        assert_eq!(ids(&list), ["a", "b", "c"]);
+        let d = Some(("d".to_string(), 0));
+        apply_entry_change(&mut list, 9, &None, &d, id_of).unwrap();
+        assert_eq!(ids(&list), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn entry_changes_made_outside_are_refused() {
+        let mut list = entries(&["a"]);
+        let missing = Some(("x".to_string(), 0));
+        assert!(matches!(
+            apply_entry_change(&mut list, 0, &missing, &None, id_of),
+            Err(TermnoteError::Validation(_))
+        ));
+        let existing = Some(("a".to_string(), 0));
+        assert!(matches!(
+            apply_entry_change(&mut list, 0, &None, &existing, id_of),
+            Err(TermnoteError::Validation(_))
+        ));
+        assert_eq!(ids(&list), ["a"]);
+    }
+
+    #[test]
+    fn searches_title_description_and_category() {
+        let mut processor = processor();
+        add(&mut processor, "List", "files").unwrap();
+        add(&mut processor, "Ping", "network").unwrap();
+        let titles = |query: &str| -> Vec<String> {
+            processor
+                .search_by_title_description_category(query)
+                .unwrap()
+                .into_iter()
+                .map(|topic| topic.title)
+                .collect()
+        };
+        assert_eq!(titles("PING"), ["Ping"]);
+        assert_eq!(titles("list desc"), ["List"]);
+        assert_eq!(titles("files"), ["List"]);
+        assert_eq!(titles("description"), ["List", "Ping"]);
+        assert!(titles("missing").is_empty());
+    }
+
+    #[test]
+    fn adds_updates_and_removes_links() {
+        let mut processor = processor();
+        let add_url = |processor: &mut CRUDProcessor, title: &str| {
+            processor.add_url(
+                title.to_string(),
+                "https://example.com".to_string(),
+                Vec::new(),
+                Origin::Global,
+            )
+        };
+        let id = add_url(&mut processor, "Docs").unwrap();
+        assert!(matches!(
+            add_url(&mut processor, "DOCS"),
+            Err(TermnoteError::Duplicate(_))
+        ));
+        let url = "https://example.org".to_string();
+        processor
+            .update_url("docs", None, Some(&url), None)
+            .unwrap();
+        assert_eq!(processor.search_links_by_title("doc").unwrap()[0].url, url);
+        add_url(&mut processor, "Blog").unwrap();
+        let renamed = "blog".to_string();
+        assert!(matches!(
+            processor.update_url(&id, Some(&renamed), None, None),
+            Err(TermnoteError::Duplicate(_))
+        ));
+        let renamed = "DOCS".to_string();
+        processor
+            .update_url(&id, Some(&renamed), None, None)
+            .unwrap();
+        processor.remove_url(&id).unwrap();
+        assert!(matches!(
+            processor.remove_url(&id),
+            Err(TermnoteError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn tags_are_normalized_and_counted() {
+        let mut processor = processor();
+        let id = processor
+            .add(
+                "List".to_string(),
+                String::new(),
+                "ls".to_string(),
+                "files".to_string(),
+                TopicDetails {
+                    tags: Some(vec!["Files, disk".to_string(), "files".to_string()]),
+                    ..TopicDetails::default()
+                },
+                Origin::Global,
+            )
+            .unwrap();
+        assert_eq!(processor.find_topic(&id).unwrap().tags, ["files", "disk"]);
+        processor
+            .add_url(
+                "Docs".to_string(),
+                "https://example.com".to_string(),
+                vec![" DISK ".to_string()],
+                Origin::Global,
+            )
+            .unwrap();
+        assert_eq!(
+            processor.tag_counts(),
+            [("disk".to_string(), 2), ("files".to_string(), 1)]
+        );
+        processor
+            .update(
+                &id,
+                None,
+                None,
+                None,
+                None,
+                TopicDetails {
+                    tags: Some(Vec::new()),
+                    ..TopicDetails::default()
+                },
+            )
+            .unwrap();
+        assert!(processor.find_topic(&id).unwrap().tags.is_empty());
+    }
+
+    #[test]
+    fn own_topics_shadow_shared_ones() {
+        let mut processor = with_shared(&[("Deploy", "team"), ("Build", "team")]);
+        add(&mut processor, "deploy", "mine").unwrap();
+        let topic = processor.find_topic("Deploy").unwrap();
+        assert_eq!(
+            (topic.origin, topic.category.as_str()),
+            (Origin::Global, "mine")
+        );
+        let origins: Vec<Origin> = processor
+            .topics()
+            .iter()
+            .map(|topic| topic.origin)
+            .collect();
+        assert_eq!(origins, [Origin::Global, Origin::Shared, Origin::Shared]);
+    }
+
+    #[test]
+    fn shared_topics_are_read_only_until_forked() {
+        let mut processor = with_shared(&[("Build", "team")]);
+        assert!(matches!(
+            processor.remove("build"),
+            Err(TermnoteError::Validation(_))
+        ));
+        let command = "make -j8".to_string();
+        assert!(matches!(
+            processor.update(
+                "build",
+                None,
+                None,
+                Some(&command),
+                None,
+                TopicDetails::default()
+            ),
+            Err(TermnoteError::Validation(_))
+        ));
+
+        let id = processor.fork("build", Origin::Global).unwrap();
+        let copy = processor.find_topic("build").unwrap();
+        assert_eq!(
+            (copy.id.as_str(), copy.origin),
+            (id.as_str(), Origin::Global)
+        );
+        processor
+            .update(
+                "build",
+                None,
+                None,
+                Some(&command),
+                None,
+                TopicDetails::default(),
+            )
+            .unwrap();
+        assert_eq!(processor.find_topic(&id).unwrap().command, "make -j8");
+        assert!(matches!(
+            processor.fork(&id, Origin::Global),
+            Err(TermnoteError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn project_library_is_layered_over_the_global_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let local_path = dir.path().join(LOCAL_FILE);
+        let mut processor = processor();
+        processor.journal = Some(Journal::new(&dir.path().join("db.journal.json"), 10));
+        processor.open_local(&local_path).unwrap();
+        add(&mut processor, "List", "files").unwrap();
+        processor
+            .add(
+                "Build".to_string(),
+                "Build description".to_string(),
+                "cargo build".to_string(),
+                "rust".to_string(),
+                TopicDetails::default(),
+                Origin::Local,
+            )
+            .unwrap();
+        let origins: Vec<_> = processor
+            .topics()
+            .into_iter()
+            .map(|topic| (topic.title, topic.origin))
+            .collect();
+        assert_eq!(
+            origins,
+            [
+                ("Build".to_string(), Origin::Local),
+                ("List".to_string(), Origin::Global)
+            ]
+        );
+        let stored = ObjectDB::read_library(&local_path).unwrap();
+        assert_eq!(stored.topics[0].title, "Build");
+        assert!(processor.database.library().topics[0].title == "List");
+
+        processor.remove("build").unwrap();
+        assert!(ObjectDB::read_library(&local_path)
+            .unwrap()
+            .topics
+            .is_empty());
+        processor.undo().unwrap();
+        assert_eq!(processor.find_topic("build").unwrap().origin, Origin::Local);
+        assert_eq!(processor.database.library().topics.len(), 1);
+    }
+}
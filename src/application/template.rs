@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use crate::TermnoteError;
+
+/// A value to fill into a command, written as `<name>` or `{{name}}`.
+///
+/// `<name=default>` gives a default value and `<name:a|b|c>` restricts the
+/// value to the listed choices, optionally with a default (`<name:a|b=a>`).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Placeholder {
+    pub name: String,
+    pub default: Option<String>,
+    pub choices: Vec<String>,
+}
+
+impl Placeholder {
+    /// Checks the value against the choices, if any.
+    pub fn validate(&self, value: &str) -> Result<(), TermnoteError> {
+        if !self.choices.is_empty() && !self.choices.iter().any(|choice| choice == value) {
+            return Err(TermnoteError::Validation(format!(
+                "'{}' is not a valid value for {}, choose one of {}",
+                value,
+                self.name,
+                self.choices.join(", ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Text(String),
+    /// Index into the placeholders of the template.
+    Placeholder(usize),
+}
+
+/// A command split into literal text and placeholders.
+#[derive(Clone, Debug)]
+pub struct CommandTemplate {
+    segments: Vec<Segment>,
+    placeholders: Vec<Placeholder>,
+}
+
+impl CommandTemplate {
+    /// Parses the placeholders of a command. Anything which is not a valid
+    /// placeholder, e.g. the shell redirections in `sort <in >out`, is kept
+    /// as literal text.
+    pub fn parse(command: &str) -> Self {
+        let mut segments = Vec::new();
+        let mut placeholders: Vec<Placeholder> = Vec::new();
+        let mut text = String::new();
+        let mut rest = command;
+        while let Some(ch) = rest.chars().next() {
+            let Some((placeholder, len)) = parse_placeholder(rest) else {
+                text.push(ch);
+                rest = &rest[ch.len_utf8()..];
+                continue;
+            };
+            if !text.is_empty() {
+                segments.push(Segment::Text(std::mem::take(&mut text)));
+            }
+            // a placeholder used several times is asked for once
+            let index = match placeholders
+                .iter()
+                .position(|known| known.name == placeholder.name)
+            {
+                Some(index) => index,
+                None => {
+                    placeholders.push(placeholder);
+                    placeholders.len() - 1
+                }
+            };
+            segments.push(Segment::Placeholder(index));
+            rest = &rest[len..];
+        }
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        Self {
+            segments,
+            placeholders,
+        }
+    }
+
+    pub fn placeholders(&self) -> &[Placeholder] {
+        &self.placeholders
+    }
+
+    pub fn has_placeholders(&self) -> bool {
+        !self.placeholders.is_empty()
+    }
+
+    /// Fills in the placeholders, taking each value from `values` or, if it
+    /// is not given there, from `ask`.
+    pub fn fill(
+        &self,
+        values: &HashMap<String, String>,
+        mut ask: impl FnMut(&Placeholder) -> Result<String, TermnoteError>,
+    ) -> Result<String, TermnoteError> {
+        let mut filled = Vec::new();
+        for placeholder in &self.placeholders {
+            let value = match values.get(&placeholder.name) {
+                Some(value) => value.clone(),
+                None => ask(placeholder)?,
+            };
+            placeholder.validate(&value)?;
+            filled.push(value);
+        }
+        Ok(self
+            .segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Text(text) => text.as_str(),
+                Segment::Placeholder(index) => filled[*index].as_str(),
+            })
+            .collect())
+    }
+}
+
+/// Parses a placeholder at the start of `input`, returning it and its length.
+fn parse_placeholder(input: &str) -> Option<(Placeholder, usize)> {
+    if let Some(body) = input.strip_prefix("{{") {
+        let end = body.find("}}")?;
+        return parse_spec(body[..end].trim()).map(|placeholder| (placeholder, end + 4));
+    }
+    let body = input.strip_prefix('<')?;
+    let end = body.find('>')?;
+    parse_spec(&body[..end]).map(|placeholder| (placeholder, end + 2))
+}
+
+/// Parses `name`, `name=default`, `name:a|b` or `name:a|b=default`.
+fn parse_spec(spec: &str) -> Option<Placeholder> {
+    let name_len = spec
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(spec.len());
+    let name = &spec[..name_len];
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        return None;
+    }
+    let rest = &spec[name_len..];
+    let (choices, default) = if let Some(list) = rest.strip_prefix(':') {
+        let (list, default) = match list.split_once('=') {
+            Some((list, default)) => (list, Some(default.to_string())),
+            None => (list, None),
+        };
+        let choices: Vec<String> = list
+            .split('|')
+            .map(str::trim)
+            .filter(|choice| !choice.is_empty())
+            .map(str::to_string)
+            .collect();
+        if choices.is_empty() {
+            return None;
+        }
+        (choices, default)
+    } else if let Some(default) = rest.strip_prefix('=') {
+        (Vec::new(), Some(default.to_string()))
+    } else if rest.is_empty() {
+        (Vec::new(), None)
+    } else {
+        return None;
+    };
+    Some(Placeholder {
+        name: name.to_string(),
+        default,
+        choices,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn no_answer(placeholder: &Placeholder) -> Result<String, TermnoteError> {
+        panic!("asked for {}", placeholder.name)
+    }
+
+    #[test]
+    fn parses_both_placeholder_syntaxes() {
+        let template = CommandTemplate::parse("ssh <user=root>@{{ host }} -p <port:22|2222=22>");
+        assert_eq!(
+            template.placeholders(),
+            [
+                Placeholder {
+                    name: "user".to_string(),
+                    default: Some("root".to_string()),
+                    choices: Vec::new(),
+                },
+                Placeholder {
+                    name: "host".to_string(),
+                    default: None,
+                    choices: Vec::new(),
+                },
+                Placeholder {
+                    name: "port".to_string(),
+                    default: Some("22".to_string()),
+                    choices: vec!["22".to_string(), "2222".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_shell_syntax_as_text() {
+        for command in [
+            "sort <in >out",
+            "echo {{}}",
+            "cat < file",
+            "a <1b>",
+            "echo <x:>",
+        ] {
+            let template = CommandTemplate::parse(command);
+            assert!(!template.has_placeholders(), "{}", command);
+            assert_eq!(template.fill(&HashMap::new(), no_answer).unwrap(), command);
+        }
+    }
+
+    #[test]
+    fn fills_repeated_placeholders_once() {
+        let template = CommandTemplate::parse("cp <file> <file>.bak && ls <dir>");
+        let mut asked = Vec::new();
+        let filled = template
+            .fill(&values(&[("file", "a.txt")]), |placeholder| {
+                asked.push(placeholder.name.clone());
+                Ok("/tmp".to_string())
+            })
+            .unwrap();
+        assert_eq!(filled, "cp a.txt a.txt.bak && ls /tmp");
+        assert_eq!(asked, ["dir"]);
+    }
+
+    #[test]
+    fn rejects_values_outside_the_choices() {
+        let template = CommandTemplate::parse("deploy <env:dev|prod>");
+        assert_eq!(
+            template
+                .fill(&values(&[("env", "prod")]), no_answer)
+                .unwrap(),
+            "deploy prod"
+        );
+        assert!(matches!(
+            template.fill(&values(&[("env", "test")]), no_answer),
+            Err(TermnoteError::Validation(_))
+        ));
+    }
+}
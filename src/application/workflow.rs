@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+
+use crate::application::{CommandTemplate, Outcome};
+use crate::{Placeholder, Step, TermnoteError};
+
+/// Answer to the confirmation asked before each step.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StepAnswer {
+    Run,
+    Skip,
+    /// Stops the workflow, the remaining steps are not run.
+    Abort,
+}
+
+/// How a step of a workflow ended.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum StepStatus {
+    /// Ran and exited, successfully or not.
+    Exited(Outcome),
+    Skipped,
+    /// Not reached because an earlier step failed or the workflow was aborted.
+    NotRun,
+}
+
+pub struct StepResult {
+    pub step: Step,
+    pub status: StepStatus,
+}
+
+impl StepResult {
+    pub fn failed(&self) -> bool {
+        matches!(&self.status, StepStatus::Exited(outcome) if !outcome.success())
+    }
+}
+
+/// Fills in the placeholders of all steps, asking for each name only once
+/// even if several steps use it.
+pub fn fill_steps(
+    steps: &[Step],
+    values: &HashMap<String, String>,
+    mut ask: impl FnMut(&Placeholder) -> Result<String, TermnoteError>,
+) -> Result<Vec<Step>, TermnoteError> {
+    let mut values = values.clone();
+    let mut filled = Vec::new();
+    for step in steps {
+        let template = CommandTemplate::parse(&step.command);
+        for placeholder in template.placeholders() {
+            if !values.contains_key(&placeholder.name) {
+                let value = ask(placeholder)?;
+                placeholder.validate(&value)?;
+                values.insert(placeholder.name.clone(), value);
+            }
+        }
+        filled.push(Step {
+            description: step.description.clone(),
+            command: template.fill(&values, |placeholder| {
+                Err(TermnoteError::Validation(format!(
+                    "No value given for {}",
+                    placeholder.name
+                )))
+            })?,
+        });
+    }
+    Ok(filled)
+}
+
+/// Runs the steps one after the other, asking `confirm` before each of them
+/// and stopping at the first one which fails.
+pub fn run_steps(
+    steps: &[Step],
+    mut confirm: impl FnMut(usize, &Step) -> Result<StepAnswer, TermnoteError>,
+    mut run: impl FnMut(&Step) -> Result<Outcome, TermnoteError>,
+) -> Result<Vec<StepResult>, TermnoteError> {
+    let mut results: Vec<StepResult> = Vec::new();
+    let mut stopped = false;
+    for (index, step) in steps.iter().enumerate() {
+        let status = if stopped {
+            StepStatus::NotRun
+        } else {
+            match confirm(index, step)? {
+                StepAnswer::Run => StepStatus::Exited(run(step)?),
+                StepAnswer::Skip => StepStatus::Skipped,
+                StepAnswer::Abort => {
+                    stopped = true;
+                    StepStatus::NotRun
+                }
+            }
+        };
+        let result = StepResult {
+            step: step.clone(),
+            status,
+        };
+        stopped |= result.failed();
+        results.push(result);
+    }
+    Ok(results)
+}
+
+/// One line per step telling how it ended.
+pub fn summarize_steps(results: &[StepResult]) -> String {
+    results
+        .iter()
+        .enumerate()
+        .map(|(index, result)| {
+            let status = match &result.status {
+                StepStatus::Exited(outcome) if outcome.success() => "ok".to_string(),
+                StepStatus::Exited(outcome) if outcome.timed_out => "timed out".to_string(),
+                StepStatus::Exited(outcome) => match outcome.status.code() {
+                    Some(code) => format!("failed with exit code {}", code),
+                    None => "killed by a signal".to_string(),
+                },
+                StepStatus::Skipped => "skipped".to_string(),
+                StepStatus::NotRun => "not run".to_string(),
+            };
+            format!("{}. {}: {}", index + 1, step_label(&result.step), status)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// The description of the step, or its command if it has none.
+pub fn step_label(step: &Step) -> &str {
+    if step.description.is_empty() {
+        &step.command
+    } else {
+        &step.description
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::{run_cmd, ExecContext};
+
+    fn steps(commands: &[&str]) -> Vec<Step> {
+        commands
+            .iter()
+            .map(|command| Step {
+                description: String::new(),
+                command: command.to_string(),
+            })
+            .collect()
+    }
+
+    fn run(step: &Step) -> Result<Outcome, TermnoteError> {
+        run_cmd(&step.command, &ExecContext::default())
+    }
+
+    fn statuses(results: &[StepResult]) -> Vec<String> {
+        results
+            .iter()
+            .map(|result| match &result.status {
+                StepStatus::Exited(outcome) => format!("exit {}", outcome.exit_code()),
+                StepStatus::Skipped => "skipped".to_string(),
+                StepStatus::NotRun => "not run".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn placeholders_are_asked_once_for_all_steps() {
+        let mut asked = Vec::new();
+        let filled = fill_steps(
+            &steps(&["git checkout <branch>", "git push origin <branch>"]),
+            &HashMap::new(),
+            |placeholder| {
+                asked.push(placeholder.name.clone());
+                Ok("main".to_string())
+            },
+        )
+        .unwrap();
+        assert_eq!(asked, ["branch"]);
+        assert_eq!(
+            Step::chain(&filled),
+            "git checkout main && git push origin main"
+        );
+    }
+
+    #[test]
+    fn stops_at_the_first_failing_step() {
+        let results = run_steps(
+            &steps(&["true", "exit 3", "true"]),
+            |_, _| Ok(StepAnswer::Run),
+            run,
+        )
+        .unwrap();
+        assert_eq!(statuses(&results), ["exit 0", "exit 3", "not run"]);
+        assert_eq!(
+            summarize_steps(&results),
+            "1. true: ok\n2. exit 3: failed with exit code 3\n3. true: not run"
+        );
+    }
+
+    #[test]
+    fn steps_can_be_skipped_or_aborted() {
+        let answers = [StepAnswer::Skip, StepAnswer::Run, StepAnswer::Abort];
+        let results = run_steps(
+            &steps(&["exit 1", "true", "true", "true"]),
+            |index, _| Ok(answers[index]),
+            run,
+        )
+        .unwrap();
+        assert_eq!(
+            statuses(&results),
+            ["skipped", "exit 0", "not run", "not run"]
+        );
+    }
+}
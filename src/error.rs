@@ -0,0 +1,99 @@
+use std::fmt::{Display, Formatter};
+use std::{error::Error, io};
+
+use crate::adapter::CURRENT_VERSION;
+
+#[derive(Debug)]
+pub enum TermnoteError {
+    /// Reading or writing files or the terminal failed.
+    Io(io::Error),
+    /// The stored library could not be (de)serialized.
+    Parse(String),
+    /// The database backend reported an error.
+    Database(String),
+    /// The addressed topic or link does not exist.
+    NotFound(String),
+    /// An entry with the same title already exists.
+    Duplicate(String),
+    /// Invalid arguments or input.
+    Validation(String),
+    /// The environment or configuration is unusable, e.g. no home directory.
+    Config(String),
+    /// The library was written by a newer termnote with the given format version.
+    UnsupportedVersion(u32),
+    /// Syncing the library through git failed.
+    Sync(String),
+    /// Encrypting or decrypting the library failed, e.g. with a wrong passphrase.
+    Encryption(String),
+    /// An executed command did not succeed, `tn` exits with its exit code.
+    Command(i32),
+}
+
+impl TermnoteError {
+    /// Process exit code reported for this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TermnoteError::Validation(_) => 2,
+            TermnoteError::NotFound(_) => 3,
+            TermnoteError::Duplicate(_) => 4,
+            TermnoteError::Parse(_) => 5,
+            TermnoteError::Io(_) => 6,
+            TermnoteError::Database(_) => 7,
+            TermnoteError::Config(_) => 8,
+            TermnoteError::UnsupportedVersion(_) => 9,
+            TermnoteError::Sync(_) => 10,
+            TermnoteError::Encryption(_) => 11,
+            TermnoteError::Command(code) => *code,
+        }
+    }
+}
+
+impl Display for TermnoteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TermnoteError::Io(e) => write!(f, "I/O failure: {}", e),
+            TermnoteError::Parse(msg) => write!(f, "Could not parse library: {}", msg),
+            TermnoteError::Database(msg) => write!(f, "Database failure: {}", msg),
+            TermnoteError::Sync(msg) => write!(f, "Sync failure: {}", msg),
+            TermnoteError::Command(code) => write!(f, "The command exited with code {}", code),
+            TermnoteError::UnsupportedVersion(version) => write!(
+                f,
+                "Library format version {} is newer than the supported version {}, please upgrade termnote",
+                version, CURRENT_VERSION
+            ),
+            TermnoteError::NotFound(msg)
+            | TermnoteError::Duplicate(msg)
+            | TermnoteError::Validation(msg)
+            | TermnoteError::Config(msg)
+            | TermnoteError::Encryption(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl Error for TermnoteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TermnoteError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TermnoteError {
+    fn from(e: io::Error) -> Self {
+        TermnoteError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for TermnoteError {
+    fn from(e: serde_json::Error) -> Self {
+        TermnoteError::Parse(e.to_string())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for TermnoteError {
+    fn from(e: rusqlite::Error) -> Self {
+        TermnoteError::Database(e.to_string())
+    }
+}
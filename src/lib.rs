@@ -1,14 +1,71 @@
 mod adapter;
 mod application;
+mod error;
+pub use adapter::danger_reasons;
+pub use adapter::display_error;
 pub use adapter::display_text;
+pub use adapter::display_warning;
+pub use adapter::generate_id;
+pub use adapter::normalize_tags;
+pub use adapter::prompt_confirmation;
+pub use adapter::prompt_input;
+pub use adapter::risky_patterns;
+pub use adapter::Backend;
+pub use adapter::Backup;
+pub use adapter::BackupStore;
+pub use adapter::Change;
+pub use adapter::Cipher;
+pub use adapter::Config;
+pub use adapter::Execution;
+pub use adapter::ExecutionLog;
+pub use adapter::GitSync;
+pub use adapter::Journal;
+pub use adapter::JournalEntry;
+pub use adapter::KeySource;
 pub use adapter::Library;
 pub use adapter::Link;
+pub use adapter::MemoryDB;
 pub use adapter::MenuEvent;
 pub use adapter::ObjectDB;
+pub use adapter::Origin;
+pub use adapter::ProfileStore;
+pub use adapter::Recovery;
+pub use adapter::Repository;
+pub use adapter::Settings;
+pub use adapter::Shell;
+#[cfg(feature = "sqlite")]
+pub use adapter::SqliteDB;
+pub use adapter::Step;
 pub use adapter::TerminalUI;
 pub use adapter::TerminalUrlUI;
 pub use adapter::Topic;
+pub use application::deserialize_backups;
+pub use application::deserialize_execution;
+pub use application::deserialize_executions;
+pub use application::deserialize_library_diff;
 pub use application::deserialize_links;
 pub use application::deserialize_topics;
+pub use application::diagnose;
+pub use application::exit_code;
+pub use application::fill_steps;
+pub use application::merge_libraries;
 pub use application::run_cmd;
+pub use application::run_steps;
+pub use application::sort_links;
+pub use application::sort_topics;
+pub use application::step_label;
+pub use application::summarize_steps;
 pub use application::CRUDProcessor;
+pub use application::CommandTemplate;
+pub use application::Diagnosis;
+pub use application::ExecContext;
+pub use application::Finding;
+pub use application::LibraryDiff;
+pub use application::Outcome;
+pub use application::Placeholder;
+pub use application::SortOrder;
+pub use application::StepAnswer;
+pub use application::StepResult;
+pub use application::StepStatus;
+pub use application::TopicDetails;
+pub use error::TermnoteError;
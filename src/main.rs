@@ -1,204 +1,1152 @@
-use clap::{arg, ArgMatches, Command};
+use chrono::{DateTime, Local, NaiveDate, TimeZone, Utc};
+use clap::{arg, value_parser, Arg, ArgAction, ArgMatches, Command};
 use ratatui::prelude::CrosstermBackend;
 use ratatui::Terminal;
-use std::error::Error;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
 use std::io::Stdout;
-use termnote::{display_text, run_cmd, CRUDProcessor, MenuEvent, TerminalUI};
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+use termnote::{
+    danger_reasons, display_error, display_text, display_warning, fill_steps, normalize_tags,
+    prompt_confirmation, prompt_input, run_steps, sort_links, sort_topics, summarize_steps,
+    Backend, BackupStore, CRUDProcessor, Cipher, CommandTemplate, Config, ExecContext, MenuEvent,
+    ObjectDB, Origin, Outcome, Placeholder, ProfileStore, Shell, SortOrder, Step, StepAnswer,
+    StepStatus, TerminalUI, TerminalUrlUI, TermnoteError, Topic, TopicDetails,
+};
+
+fn sort_arg() -> Arg {
+    arg!(-s --sort <ORDER> "Order of the entries: added (default), recent or used")
+        .value_parser(["added", "recent", "used"])
+}
+
+fn tag_arg(help: &'static str) -> Arg {
+    arg!(--tag <TAG>).help(help).action(ArgAction::Append)
+}
+
+/// Values of the `--tag` option, `None` if it was not given.
+fn tag_values(matches: &ArgMatches) -> Option<Vec<String>> {
+    matches
+        .get_many::<String>("tag")
+        .map(|tags| tags.cloned().collect())
+}
+
+fn step_arg(help: &'static str) -> Arg {
+    arg!(--step <STEP>).help(help).action(ArgAction::Append)
+}
+
+/// Steps given with `--step "DESCRIPTION :: COMMAND"`, `None` if the option
+/// was not given. Empty values are dropped, so `--step ""` removes all steps.
+fn step_values(matches: &ArgMatches) -> Result<Option<Vec<Step>>, TermnoteError> {
+    let Some(values) = matches.get_many::<String>("step") else {
+        return Ok(None);
+    };
+    let mut steps = Vec::new();
+    for value in values.filter(|value| !value.trim().is_empty()) {
+        let (description, command) = value.split_once(" :: ").unwrap_or(("", value));
+        if command.trim().is_empty() {
+            return Err(TermnoteError::Validation(format!(
+                "The step '{}' has no command",
+                value
+            )));
+        }
+        steps.push(Step {
+            description: description.trim().to_string(),
+            command: command.trim().to_string(),
+        });
+    }
+    Ok(Some(steps))
+}
+
+/// Options setting where and with what the commands of a topic run.
+fn context_args(command: Command, clearable: bool) -> Command {
+    let (cwd_help, env_help) = if clearable {
+        (
+            "Directory to run the command in, \"\" runs it in the current one",
+            "Replace the environment variables, repeat as NAME=VALUE for several, \"\" removes all",
+        )
+    } else {
+        (
+            "Directory to run the command in instead of the current one",
+            "Environment variable to set as NAME=VALUE, repeat for several",
+        )
+    };
+    let mut shells = Shell::NAMES.to_vec();
+    if clearable {
+        shells.push("default");
+    }
+    command
+        .arg(arg!(--cwd <DIR>).help(cwd_help))
+        .arg(arg!(--env <VAR>).help(env_help).action(ArgAction::Append))
+        .arg(
+            arg!(--shell <SHELL> "Shell to run the command with instead of $SHELL")
+                .value_parser(shells),
+        )
+}
+
+/// Working directory given with `--cwd`, `Some(None)` if it is empty.
+fn cwd_value(matches: &ArgMatches) -> Option<Option<String>> {
+    matches
+        .get_one::<String>("cwd")
+        .map(|cwd| Some(cwd.trim().to_string()).filter(|cwd| !cwd.is_empty()))
+}
+
+/// Variables given with `--env NAME=VALUE`, `None` if the option was not given.
+fn env_values(matches: &ArgMatches) -> Result<Option<BTreeMap<String, String>>, TermnoteError> {
+    let Some(values) = matches.get_many::<String>("env") else {
+        return Ok(None);
+    };
+    let mut env = BTreeMap::new();
+    for value in values.filter(|value| !value.is_empty()) {
+        match value.split_once('=') {
+            Some((name, variable)) if !name.trim().is_empty() => {
+                env.insert(name.trim().to_string(), variable.to_string());
+            }
+            _ => {
+                return Err(TermnoteError::Validation(format!(
+                    "Expected NAME=VALUE instead of '{}'",
+                    value
+                )))
+            }
+        }
+    }
+    Ok(Some(env))
+}
+
+/// Shell given with `--shell`, `Some(None)` for the default one.
+fn shell_value(matches: &ArgMatches) -> Result<Option<Option<Shell>>, TermnoteError> {
+    match matches.get_one::<String>("shell").map(String::as_str) {
+        None => Ok(None),
+        Some("default") => Ok(Some(None)),
+        Some(name) => Ok(Some(Some(Shell::from_name(name)?))),
+    }
+}
+
+/// Tags an entry must carry to be shown, normalized like stored tags.
+fn tag_filter(matches: &ArgMatches) -> Vec<String> {
+    normalize_tags(&tag_values(matches).unwrap_or_default())
+}
+
+/// The search query, which may be left out when filtering by tag.
+fn search_query(matches: &ArgMatches) -> Result<String, TermnoteError> {
+    match matches.get_one::<String>("query") {
+        Some(query) => Ok(query.to_string()),
+        None if matches.contains_id("tag") => Ok(String::new()),
+        None => Err(TermnoteError::Validation(
+            "Must provide a query".to_string(),
+        )),
+    }
+}
+
+fn sort_order(matches: &ArgMatches) -> Result<SortOrder, TermnoteError> {
+    match matches.get_one::<String>("sort") {
+        Some(name) => SortOrder::from_name(name),
+        None => Ok(SortOrder::Added),
+    }
+}
+
+fn capture_arg() -> Arg {
+    arg!(--capture "Record the end of the output in the history, the command then does not write to the terminal directly")
+}
+
+fn number_arg() -> Arg {
+    arg!(<N> "Number of the execution in `tn history`").value_parser(value_parser!(u64))
+}
+
+/// Start of the day given with `--since`.
+fn since_value(matches: &ArgMatches) -> Result<Option<DateTime<Utc>>, TermnoteError> {
+    let Some(date) = matches.get_one::<String>("since") else {
+        return Ok(None);
+    };
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| {
+            Local
+                .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+                .earliest()
+        })
+        .map(|start| Some(start.with_timezone(&Utc)))
+        .ok_or_else(|| {
+            TermnoteError::Validation(format!(
+                "Expected a date as YYYY-MM-DD instead of '{}'",
+                date
+            ))
+        })
+}
 
 fn cmd() -> Command {
     Command::new("tn")
         .about("A terminal CLI tool to note the commands and urls")
         .author("Slaytanic87")
-        .subcommand(
+        .arg(
+            arg!(--db <PATH> "Path of the library database file (default: $TERMNOTE_DB or ~/.termnote/db.json)")
+                .global(true),
+        )
+        .arg(
+            arg!(--profile <NAME> "Profile whose library to use (default: $TERMNOTE_PROFILE or the one set with `tn profile use`)")
+                .global(true),
+        )
+        .subcommand(context_args(
             Command::new("add")
                 .about("Add a new command to the list")
                 .arg(arg!(-t --title <TITLE>))
                 .arg(arg!(-d --description <DESCRIPTION>))
-                .arg(arg!(-c --command <COMMAND>))
-                .arg(arg!(-k --category <CATEGORY>)),
-        )
-        .subcommand(
+                .arg(arg!(-c --command <COMMAND> "(mandatory unless --step is given)"))
+                .arg(arg!(-k --category <CATEGORY>))
+                .arg(tag_arg("Tag of the command, repeat or separate with commas for several"))
+                .arg(arg!(--dangerous "Ask for a typed confirmation before executing the command"))
+                .arg(step_arg("Step of a workflow as \"DESCRIPTION :: COMMAND\" or COMMAND, repeat for each step in order"))
+                .arg(arg!(-l --local "Add to the project library (.termnote.json) instead of the global one")),
+            false,
+        ))
+        .subcommand(context_args(
             Command::new("update")
                 .about("Update a noted command")
-                .arg(arg!(-i --index <INDEX> "(mandatory) Index of the command to update"))
+                .arg(arg!(-i --id <ID> "(mandatory) ID or title of the command to update").alias("index"))
                 .arg(arg!(-t --title <TITLE>))
                 .arg(arg!(-d --description <DESCRIPTION>))
                 .arg(arg!(-c --command <COMMAND>))
-                .arg(arg!(-k --category <CATEGORY>)),
-        )
+                .arg(arg!(-k --category <CATEGORY>))
+                .arg(tag_arg("Replace the tags, repeat or separate with commas for several, \"\" removes all"))
+                .arg(
+                    arg!(--dangerous <BOOL> "Whether to ask for a typed confirmation before executing the command")
+                        .value_parser(value_parser!(bool)),
+                )
+                .arg(step_arg("Replace the steps, repeat for each step in order, \"\" removes all")),
+            true,
+        ))
         .subcommand(
             Command::new("remove")
-                .about("Remove a noted command by title or index")
-                .arg(arg!(-i --index <INDEX> "(mandatory) Index of the command to remove")),
+                .about("Remove a noted command by ID or title")
+                .arg(arg!(-i --id <ID> "(mandatory) ID or title of the command to remove").alias("index")),
         )
         .subcommand(Command::new("search")
                     .about("Search commands by title or description")
-                    .arg(arg!(-q --query <QUERY> "(mandatory) Query string to search for in titles or descriptions"))
+                    .arg(arg!(-q --query <QUERY> "(mandatory unless --tag is given) Query string to search for in titles or descriptions"))
+                    .arg(tag_arg("Only show commands with this tag, repeat for several"))
+                    .arg(sort_arg())
+        )
+        .subcommand(
+            Command::new("list")
+                .about("List all noted commands")
+                .arg(sort_arg())
+                .arg(tag_arg("Only show commands with this tag, repeat for several")),
+        )
+        .subcommand(Command::new("tags").about("List all tags with the number of commands and links carrying them"))
+        .subcommand(
+            Command::new("fork")
+                .about("Copy a command of a shared library into your library to edit it")
+                .arg(arg!(<ID> "ID or title of the shared command"))
+                .arg(arg!(-l --local "Copy into the project library (.termnote.json) instead of the global one")),
+        )
+        .subcommand(Command::new("encrypt").about("Encrypt the library with a passphrase or keyfile"))
+        .subcommand(Command::new("decrypt").about("Store the library in plaintext again"))
+        .subcommand(
+            Command::new("doctor")
+                .about("Recover a damaged library and check it for problems")
+                .arg(arg!(-y --yes "Apply the fixes without asking for confirmation")),
+        )
+        .subcommand(Command::new("undo").about("Undo the last change to the library"))
+        .subcommand(Command::new("redo").about("Redo the last undone change to the library"))
+        .subcommand(
+            Command::new("exec")
+                .about("Execute a noted command or the steps of a workflow, asking for the values of placeholders")
+                .arg(arg!(<ID> "ID or title of the command"))
+                .arg(
+                    arg!(--set <VALUE> "Value of a placeholder as NAME=VALUE instead of asking for it")
+                        .action(ArgAction::Append),
+                )
+                .arg(arg!(-y --yes "Execute the command or each step without asking for confirmation, even if it is dangerous"))
+                .arg(arg!(--"dry-run" "Show the command with its placeholders filled in and its environment without executing it"))
+                .arg(
+                    arg!(--timeout <SECS> "Terminate the command, or each step, when it runs longer")
+                        .value_parser(value_parser!(u64).range(1..)),
+                )
+                .arg(capture_arg()),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("List the commands executed through termnote, newest first")
+                .arg(arg!(--topic <ID> "Only show executions of this command, by ID or title"))
+                .arg(arg!(--failed "Only show executions which failed or timed out"))
+                .arg(arg!(--since <DATE> "Only show executions since the date, as YYYY-MM-DD"))
+                .arg(
+                    arg!(-n --limit <N> "Show at most N executions (default: 20)")
+                        .value_parser(value_parser!(usize)),
+                )
+                .subcommand(
+                    Command::new("show")
+                        .about("Show an execution with its environment and captured output")
+                        .arg(number_arg()),
+                )
+                .subcommand(
+                    Command::new("rerun")
+                        .about("Execute the command of an execution again, in the same directory and environment")
+                        .arg(number_arg())
+                        .arg(arg!(-y --yes "Execute without asking for confirmation, even if it is dangerous"))
+                        .arg(capture_arg()),
+                ),
         )
-        .subcommand(Command::new("list").about("List all noted commands"))
         .subcommand(Command::new("url")
                     .about("Link notes")
                     .subcommand(
-                        Command::new("list").about("List all noted links")
+                        Command::new("list").about("List all noted links").arg(sort_arg())
+                                .arg(tag_arg("Only show links with this tag, repeat for several"))
                     )
                     .subcommand(Command::new("add")
                                 .about("Add a new link to the list")
                                 .arg(arg!(-t --title <TITLE>))
                                 .arg(arg!(-u --url <URL>))
+                                .arg(tag_arg("Tag of the link, repeat or separate with commas for several"))
+                                .arg(arg!(-l --local "Add to the project library (.termnote.json) instead of the global one"))
                     )
                     .subcommand(Command::new("update").about("Update a noted link")
-                                .arg(arg!(-i --index <INDEX> "(mandatory) Index of the link to update"))
+                                .arg(arg!(-i --id <ID> "(mandatory) ID or title of the link to update").alias("index"))
                                 .arg(arg!(-t --title <TITLE>))
                                 .arg(arg!(-u --url <URL>))
+                                .arg(tag_arg("Replace the tags, repeat or separate with commas for several, \"\" removes all"))
+                    )
+                    .subcommand(Command::new("remove").about("Remove a noted link by ID or title")
+                                .arg(arg!(-i --id <ID> "(mandatory) ID or title of the link to remove").alias("index"))
                     )
-                    .subcommand(Command::new("remove").about("Remove a noted link by title or index")
-                                .arg(arg!(-i --index <INDEX> "(mandatory) Index of the link to remove"))
+                    .subcommand(Command::new("fork").about("Copy a link of a shared library into your library to edit it")
+                                .arg(arg!(<ID> "ID or title of the shared link"))
+                                .arg(arg!(-l --local "Copy into the project library (.termnote.json) instead of the global one"))
                     )
                     .subcommand(Command::new("search").about("Search links by title")
-                                .arg(arg!(-q --query <QUERY> "(mandatory) Query string to search for in link titles"))
+                                .arg(arg!(-q --query <QUERY> "(mandatory unless --tag is given) Query string to search for in link titles"))
+                                .arg(tag_arg("Only show links with this tag, repeat for several"))
+                                .arg(sort_arg())
+                    )
+        )
+        .subcommand(Command::new("sync")
+                    .about("Sync the library through git")
+                    .subcommand(Command::new("init")
+                                .about("Turn the termnote home directory into a git repository")
+                                .arg(arg!([REMOTE] "URL of the remote repository to sync with"))
+                    )
+                    .subcommand(Command::new("pull").about("Pull and merge the library from the remote"))
+                    .subcommand(Command::new("push").about("Push the library changes to the remote"))
+                    .subcommand(Command::new("status").about("Show unsynced changes"))
+        )
+        .subcommand(Command::new("profile")
+                    .about("Separate libraries, e.g. for work and home")
+                    .subcommand(Command::new("list").about("List all profiles, the active one marked with *"))
+                    .subcommand(Command::new("create")
+                                .about("Create a profile with an empty library")
+                                .arg(arg!(<NAME> "Name of the profile"))
+                    )
+                    .subcommand(Command::new("use")
+                                .about("Use the profile from now on")
+                                .arg(arg!(<NAME> "Name of the profile, default for the library in the home directory"))
+                    )
+                    .subcommand(Command::new("delete")
+                                .about("Delete a profile and its library")
+                                .arg(arg!(<NAME> "Name of the profile"))
+                                .arg(arg!(-y --yes "Delete without asking for confirmation"))
+                    )
+        )
+        .subcommand(Command::new("backup")
+                    .about("Library backups")
+                    .subcommand(Command::new("list").about("List all library backups, newest first"))
+                    .subcommand(Command::new("restore")
+                                .about("Restore the library from a backup")
+                                .arg(arg!(<ID> "Id of the backup to restore"))
+                                .arg(arg!(-y --yes "Restore without asking for confirmation"))
                     )
         )
 }
 
-fn restore_terminal() -> Result<(), Box<dyn Error>> {
+fn restore_terminal() {
     ratatui::restore();
-    Ok(())
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let matches: ArgMatches = cmd().get_matches();
-    let mut processor = CRUDProcessor::init();
-    let mut terminal: Terminal<CrosstermBackend<Stdout>> = ratatui::init();
-    let mut terminal_ui = TerminalUI::new(processor.database.library.topics.clone());
-    let mut terminal_url_ui =
-        termnote::TerminalUrlUI::new(processor.database.library.links.clone());
-    let message: String = match matches.subcommand() {
+fn required<'a>(matches: &'a ArgMatches, id: &str) -> Result<&'a String, TermnoteError> {
+    matches
+        .get_one::<String>(id)
+        .ok_or_else(|| TermnoteError::Validation(format!("Must provide a {}", id)))
+}
+
+/// Values given with `--set NAME=VALUE` for the placeholders of `command`.
+fn placeholder_values(
+    matches: &ArgMatches,
+    command: &str,
+) -> Result<HashMap<String, String>, TermnoteError> {
+    let template = CommandTemplate::parse(command);
+    let mut values = HashMap::new();
+    for assignment in matches.get_many::<String>("set").into_iter().flatten() {
+        let (name, value) = assignment.split_once('=').ok_or_else(|| {
+            TermnoteError::Validation(format!("Expected NAME=VALUE instead of '{}'", assignment))
+        })?;
+        if !template
+            .placeholders()
+            .iter()
+            .any(|placeholder| placeholder.name == name)
+        {
+            return Err(TermnoteError::Validation(format!(
+                "The command has no placeholder named {}",
+                name
+            )));
+        }
+        values.insert(name.to_string(), value.to_string());
+    }
+    Ok(values)
+}
+
+/// Asks for the value of a placeholder until a valid one is given.
+fn ask_placeholder(placeholder: &Placeholder) -> Result<String, TermnoteError> {
+    let mut question = placeholder.name.clone();
+    if !placeholder.choices.is_empty() {
+        question.push_str(&format!(" [{}]", placeholder.choices.join("/")));
+    }
+    if let Some(default) = &placeholder.default {
+        question.push_str(&format!(" (default: {})", default));
+    }
+    question.push(':');
+    loop {
+        let Some(answer) = prompt_input(&question)? else {
+            return Err(TermnoteError::Validation(format!(
+                "No value given for {}",
+                placeholder.name
+            )));
+        };
+        let value = match (answer.is_empty(), &placeholder.default) {
+            (true, Some(default)) => default.clone(),
+            (true, None) => continue,
+            (false, _) => answer,
+        };
+        match placeholder.validate(&value) {
+            Ok(()) => return Ok(value),
+            Err(e) => display_error(&e),
+        }
+    }
+}
+
+/// Risks of the filled-in command which were not already confirmed for the
+/// command as noted, e.g. in `tn list`.
+fn unconfirmed_risks(topic: &Topic, noted: &str, filled: &str, confirmed: bool) -> Vec<String> {
+    let mut reasons = danger_reasons(topic.dangerous, filled);
+    if confirmed {
+        let confirmed_reasons = danger_reasons(topic.dangerous, noted);
+        reasons.retain(|reason| !confirmed_reasons.contains(reason));
+    }
+    reasons
+}
+
+/// Asks to type the ID of the topic before running a dangerous command.
+fn confirm_danger(id: &str, reasons: &[String]) -> Result<bool, TermnoteError> {
+    display_warning(&format!("Dangerous command: {}", reasons.join(", ")));
+    let question = format!("Type {} to execute:", id);
+    Ok(prompt_input(&question)?.is_some_and(|answer| answer.trim() == id))
+}
+
+/// Error for a declined confirmation, which must not look like a successful run.
+fn execution_aborted() -> TermnoteError {
+    TermnoteError::Validation("Execution aborted".to_string())
+}
+
+/// Fills in the placeholders of a command and shows the result for
+/// confirmation, failing if it was declined. Dangerous commands need
+/// a typed confirmation unless `yes` is set or it was `confirmed` already,
+/// other commands without placeholders are returned as they are.
+fn prepare_command(
+    topic: &Topic,
+    command: &str,
+    values: &HashMap<String, String>,
+    yes: bool,
+    confirmed: bool,
+) -> Result<String, TermnoteError> {
+    let template = CommandTemplate::parse(command);
+    let filled = template.fill(values, ask_placeholder)?;
+    let reasons = unconfirmed_risks(topic, command, &filled, confirmed);
+    if reasons.is_empty() && !template.has_placeholders() {
+        return Ok(filled);
+    }
+    display_text(&format!("$ {}", filled));
+    let accepted = if yes {
+        true
+    } else if reasons.is_empty() {
+        prompt_confirmation("Execute this command?")?
+    } else {
+        confirm_danger(&topic.id, &reasons)?
+    };
+    if !accepted {
+        return Err(execution_aborted());
+    }
+    Ok(filled)
+}
+
+/// Asks whether to run the next step of a workflow.
+fn ask_step() -> Result<StepAnswer, TermnoteError> {
+    loop {
+        let Some(answer) = prompt_input("Run this step? (y)es, (s)kip, (a)bort:")? else {
+            return Ok(StepAnswer::Abort);
+        };
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => return Ok(StepAnswer::Run),
+            "s" | "skip" => return Ok(StepAnswer::Skip),
+            "a" | "abort" => return Ok(StepAnswer::Abort),
+            _ => {}
+        }
+    }
+}
+
+/// Runs the steps of a workflow one by one, asking before each step unless
+/// `yes` is set, and returns a summary of how every step ended. Dangerous
+/// steps need a typed confirmation up front unless `confirmed` already.
+fn run_workflow(
+    processor: &CRUDProcessor,
+    topic: &Topic,
+    context: &ExecContext,
+    values: &HashMap<String, String>,
+    yes: bool,
+    confirmed: bool,
+) -> Result<String, TermnoteError> {
+    let steps = fill_steps(&topic.steps, values, ask_placeholder)?;
+    let reasons = unconfirmed_risks(
+        topic,
+        &Step::chain(&topic.steps),
+        &Step::chain(&steps),
+        confirmed,
+    );
+    if !yes && !reasons.is_empty() && !confirm_danger(&topic.id, &reasons)? {
+        return Err(execution_aborted());
+    }
+    let results = run_steps(
+        &steps,
+        |index, step| {
+            let mut heading = format!("Step {}/{}", index + 1, steps.len());
+            if !step.description.is_empty() {
+                heading.push_str(&format!(": {}", step.description));
+            }
+            display_text(&format!("{}\n$ {}", heading, step.command));
+            if yes {
+                return Ok(StepAnswer::Run);
+            }
+            ask_step()
+        },
+        |step| processor.execute(&topic.id, &topic.title, &step.command, context),
+    )?;
+    let summary = summarize_steps(&results);
+    let failure = results.iter().find_map(|result| match &result.status {
+        StepStatus::Exited(outcome) if !outcome.success() => Some(outcome.clone()),
+        _ => None,
+    });
+    match failure {
+        Some(outcome) => {
+            display_text(&summary);
+            command_result(outcome)
+        }
+        None => Ok(summary),
+    }
+}
+
+/// Fails with the exit code of the command unless it succeeded.
+fn command_result(outcome: Outcome) -> Result<String, TermnoteError> {
+    if outcome.timed_out {
+        display_warning("The command was terminated because it exceeded the timeout");
+    }
+    if outcome.success() {
+        Ok(String::new())
+    } else {
+        Err(TermnoteError::Command(outcome.exit_code()))
+    }
+}
+
+/// The commands of the topic with their placeholders filled in, followed by
+/// the environment they would run in.
+fn dry_run(
+    topic: &Topic,
+    context: &ExecContext,
+    values: &HashMap<String, String>,
+) -> Result<String, TermnoteError> {
+    let mut lines = Vec::new();
+    if topic.steps.is_empty() {
+        let command = CommandTemplate::parse(&topic.command).fill(values, ask_placeholder)?;
+        lines.push(format!("$ {}", command));
+    } else {
+        let steps = fill_steps(&topic.steps, values, ask_placeholder)?;
+        for (index, step) in steps.iter().enumerate() {
+            lines.push(format!("{}. $ {}", index + 1, step.command));
+        }
+    }
+    let reasons = danger_reasons(topic.dangerous, &lines.join("\n"));
+    if !reasons.is_empty() {
+        lines.push(format!("dangerous: {}", reasons.join(", ")));
+    }
+    lines.push(context.describe());
+    Ok(lines.join("\n"))
+}
+
+/// Command whose placeholders `--set` may fill, all steps for a workflow.
+fn template_source(topic: &Topic) -> String {
+    if topic.steps.is_empty() {
+        topic.command.clone()
+    } else {
+        Step::chain(&topic.steps)
+    }
+}
+
+/// Library targeted by `--local`, opening or creating the project library.
+fn target_origin(
+    matches: &ArgMatches,
+    config: &Config,
+    processor: &mut CRUDProcessor,
+) -> Result<Origin, TermnoteError> {
+    if !matches.get_flag("local") {
+        return Ok(Origin::Global);
+    }
+    processor.open_local(&config.local_db_target()?)?;
+    Ok(Origin::Local)
+}
+
+/// Recovers damaged libraries before opening them, then checks the global
+/// library and offers to fix the problems found.
+fn run_doctor(matches: &ArgMatches, config: &Config) -> Result<String, TermnoteError> {
+    let mut libraries = Vec::new();
+    if config.backend == Backend::Json && config.db_path.exists() {
+        let cipher = Cipher::unlock(&config.db_path, &config.key_source())?;
+        libraries.push((config.db_path.clone(), cipher));
+    }
+    if let Some(local_path) = &config.local_db_path {
+        libraries.push((local_path.clone(), None));
+    }
+    for (path, cipher) in libraries {
+        if let Some(recovery) = ObjectDB::recover(&path, cipher)? {
+            display_text(&format!(
+                "Recovered {} topics and {} links from {}, the damaged file was moved to {}",
+                recovery.topics,
+                recovery.links,
+                path.display(),
+                recovery.damaged_path.display()
+            ));
+            if recovery.lost_topics + recovery.lost_links > 0 {
+                display_text(&format!(
+                    "Lost {} topics and {} links which could not be read, see `tn backup list` for older copies",
+                    recovery.lost_topics, recovery.lost_links
+                ));
+            }
+        }
+    }
+    let mut processor = CRUDProcessor::init(config)?;
+    let diagnosis = processor.diagnose();
+    if diagnosis.findings.is_empty() {
+        return Ok("No problems found".to_string());
+    }
+    for finding in &diagnosis.findings {
+        match &finding.fix {
+            Some(fix) => display_text(&format!("{}, fix: {}", finding.problem, fix)),
+            None => display_text(&format!("{}, fix it by hand", finding.problem)),
+        }
+    }
+    let fixes = diagnosis.fixable();
+    if fixes == 0 {
+        return Ok(String::new());
+    }
+    if !matches.get_flag("yes") && !prompt_confirmation(&format!("Apply {} fixes?", fixes))? {
+        return Ok("Fixes not applied".to_string());
+    }
+    let applied = processor.repair()?;
+    Ok(format!("Success: Applied {} fixes", applied))
+}
+
+fn execution_number(matches: &ArgMatches) -> Result<u64, TermnoteError> {
+    matches
+        .get_one::<u64>("N")
+        .copied()
+        .ok_or_else(|| TermnoteError::Validation("Must provide an execution number".to_string()))
+}
+
+/// Lists the recorded executions, shows one of them or executes its command
+/// again with the directory, shell and environment it ran with.
+fn run_history(
+    matches: &ArgMatches,
+    processor: &mut CRUDProcessor,
+) -> Result<String, TermnoteError> {
+    match matches.subcommand() {
+        Some(("show", show_matches)) => {
+            let execution = processor.find_execution(execution_number(show_matches)?)?;
+            Ok(termnote::deserialize_execution(&execution))
+        }
+        Some(("rerun", rerun_matches)) => {
+            let execution = processor.find_execution(execution_number(rerun_matches)?)?;
+            // the topic may have been removed since
+            let topic = processor.find_topic(&execution.topic_id).ok();
+            let context = ExecContext {
+                cwd: Some(execution.cwd.clone()),
+                env: execution.env.clone(),
+                shell: execution.shell,
+                timeout: None,
+                capture: rerun_matches.get_flag("capture"),
+            };
+            let reasons = danger_reasons(
+                topic.as_ref().is_some_and(|topic| topic.dangerous),
+                &execution.command,
+            );
+            display_text(&format!("$ {}\n{}", execution.command, context.describe()));
+            let accepted = if rerun_matches.get_flag("yes") {
+                true
+            } else if reasons.is_empty() {
+                prompt_confirmation("Execute this command again?")?
+            } else {
+                confirm_danger(&execution.topic_id, &reasons)?
+            };
+            if !accepted {
+                return Err(execution_aborted());
+            }
+            if topic.is_some() {
+                processor.record_topic_use(&execution.topic_id)?;
+            }
+            command_result(processor.execute(
+                &execution.topic_id,
+                &execution.title,
+                &execution.command,
+                &context,
+            )?)
+        }
+        _ => {
+            let mut executions = processor.executions()?;
+            if let Some(key) = matches.get_one::<String>("topic") {
+                let id = processor
+                    .find_topic(key)
+                    .map(|topic| topic.id)
+                    .unwrap_or_else(|_| key.to_string());
+                executions.retain(|execution| execution.topic_id == id || execution.title == *key);
+            }
+            if matches.get_flag("failed") {
+                executions.retain(|execution| !execution.success());
+            }
+            if let Some(since) = since_value(matches)? {
+                executions.retain(|execution| execution.started_at >= since);
+            }
+            executions.truncate(matches.get_one::<usize>("limit").copied().unwrap_or(20));
+            if executions.is_empty() {
+                Ok("No executions found".to_string())
+            } else {
+                Ok(termnote::deserialize_executions(&executions))
+            }
+        }
+    }
+}
+
+/// Profile commands work on the profile files only, without opening a library.
+fn run_profile(matches: &ArgMatches, config: &Config) -> Result<String, TermnoteError> {
+    let profiles = config.profiles();
+    match matches.subcommand() {
+        Some(("list", _)) => {
+            let active = config.profile_name();
+            let lines: Vec<String> = profiles
+                .list()?
+                .into_iter()
+                .map(|name| {
+                    let marker = if name == active { "*" } else { " " };
+                    format!("{} {}", marker, name)
+                })
+                .collect();
+            Ok(lines.join("\n"))
+        }
+        Some(("create", create_matches)) => {
+            let name = required(create_matches, "NAME")?;
+            ProfileStore::validate_name(name)?;
+            if profiles.exists(name) {
+                return Err(TermnoteError::Duplicate(format!(
+                    "Profile {} already exists",
+                    name
+                )));
+            }
+            CRUDProcessor::init(&config.with_profile(name))?;
+            Ok(format!("Success: Created profile {}", name))
+        }
+        Some(("use", use_matches)) => {
+            let name = required(use_matches, "NAME")?;
+            profiles.set_active(name)?;
+            Ok(format!("Success: Using profile {}", name))
+        }
+        Some(("delete", delete_matches)) => {
+            let name = required(delete_matches, "NAME")?;
+            if !delete_matches.get_flag("yes")
+                && !prompt_confirmation(&format!("Delete profile {} and its library?", name))?
+            {
+                return Ok("Delete aborted".to_string());
+            }
+            // resolved before the library file is gone, which decides its format
+            let profile_config = config.with_profile(name);
+            profiles.delete(name)?;
+            for path in [
+                profile_config.journal_path(),
+                profile_config.execution_log_path(),
+            ] {
+                if path.exists() {
+                    fs::remove_file(path)?;
+                }
+            }
+            BackupStore::new(
+                &profile_config.backup_dir(),
+                &profile_config.library_key(),
+                0,
+            )
+            .remove_all()?;
+            Ok(format!("Success: Deleted profile {}", name))
+        }
+        _ => Err(TermnoteError::Validation(
+            "Missing subcommand for profile".to_string(),
+        )),
+    }
+}
+
+fn run(matches: &ArgMatches) -> Result<String, TermnoteError> {
+    let db_arg: Option<&String> = matches.get_one::<String>("db");
+    let profile_arg: Option<&String> = matches.get_one::<String>("profile");
+    let config = Config::resolve(db_arg.map(Path::new), profile_arg.map(String::as_str))?;
+    match matches.subcommand() {
+        Some(("profile", sub_matches)) => return run_profile(sub_matches, &config),
+        Some(("doctor", sub_matches)) => return run_doctor(sub_matches, &config),
+        _ => {}
+    }
+    let mut processor = CRUDProcessor::init(&config).map_err(|e| match e {
+        TermnoteError::Parse(msg) => {
+            TermnoteError::Parse(format!("{}, run `tn doctor` to recover the library", msg))
+        }
+        e => e,
+    })?;
+    match matches.subcommand() {
         Some(("add", sub_matches)) => {
-            let title = sub_matches
-                .get_one::<String>("title")
-                .expect("Title is required");
+            let title = required(sub_matches, "title")?;
             let description = sub_matches
                 .get_one::<String>("description")
                 .map(|s| s.as_str());
-            let command: &str = sub_matches
-                .get_one::<String>("command")
-                .expect("Command is required");
-            let category: &str = sub_matches
-                .get_one::<String>("category")
-                .expect("Category is required");
-            processor.add(
+            let steps = step_values(sub_matches)?.unwrap_or_default();
+            let command = match sub_matches.get_one::<String>("command") {
+                Some(command) => command.to_string(),
+                None if !steps.is_empty() => Step::chain(&steps),
+                None => required(sub_matches, "command")?.to_string(),
+            };
+            let category: &str = required(sub_matches, "category")?;
+            let origin = target_origin(sub_matches, &config, &mut processor)?;
+            let id = processor.add(
                 title.to_string(),
                 description.unwrap_or("").to_string(),
-                command.to_string(),
+                command,
                 category.to_string(),
-            )
+                TopicDetails {
+                    tags: tag_values(sub_matches),
+                    steps: Some(steps),
+                    cwd: cwd_value(sub_matches),
+                    env: env_values(sub_matches)?,
+                    shell: shell_value(sub_matches)?,
+                    dangerous: Some(sub_matches.get_flag("dangerous")),
+                },
+                origin,
+            )?;
+            Ok(format!("Success: Added topic {}", id))
         }
         Some(("remove", sub_matches)) => {
-            let index_str: Option<String> = sub_matches.get_one::<String>("index").cloned();
-            if let Some(idx_str) = index_str {
-                if let Ok(idx) = idx_str.parse() {
-                    processor.remove_by_index(idx)
-                } else {
-                    "Invalid index number".to_string()
-                }
-            } else {
-                "Error: Must provide an index".to_string()
-            }
+            let key = required(sub_matches, "id")?;
+            processor.remove(key)?;
+            Ok("Success: Removed topic".to_string())
         }
         Some(("update", sub_matches)) => {
-            let index_str: &String = sub_matches
-                .get_one::<String>("index")
-                .expect("Index is required");
+            let key = required(sub_matches, "id")?;
             let title: Option<&String> = sub_matches.get_one::<String>("title");
             let description: Option<&String> = sub_matches.get_one::<String>("description");
-            let command: Option<&String> = sub_matches.get_one::<String>("command");
+            let steps = step_values(sub_matches)?;
+            // the command of a workflow follows its steps unless given explicitly
+            let chained = steps
+                .as_ref()
+                .filter(|steps| !steps.is_empty())
+                .map(|steps| Step::chain(steps));
+            let command: Option<&String> = sub_matches
+                .get_one::<String>("command")
+                .or(chained.as_ref());
             let category: Option<&String> = sub_matches.get_one::<String>("category");
-            if let Ok(idx) = index_str.parse() {
-                processor.update(idx, title, description, command, category)
-            } else {
-                "Invalid index number".to_string()
-            }
+            processor.update(
+                key,
+                title,
+                description,
+                command,
+                category,
+                TopicDetails {
+                    tags: tag_values(sub_matches),
+                    steps,
+                    cwd: cwd_value(sub_matches),
+                    env: env_values(sub_matches)?,
+                    shell: shell_value(sub_matches)?,
+                    dangerous: sub_matches.get_one::<bool>("dangerous").copied(),
+                },
+            )?;
+            Ok("Success: Updated topic".to_string())
         }
         Some(("search", sub_matches)) => {
-            let query: &String = sub_matches
-                .get_one::<String>("query")
-                .expect("Query is required");
-            let results = processor.search_by_title_description_category(query);
+            let query = search_query(sub_matches)?;
+            let tags = tag_filter(sub_matches);
+            let mut results = processor.search_by_title_description_category(&query)?;
+            results.retain(|topic| topic.has_tags(&tags));
+            sort_topics(&mut results, sort_order(sub_matches)?);
             if results.is_empty() {
-                "No commands found matching the query".to_string()
+                Ok("No commands found matching the query".to_string())
             } else {
-                termnote::deserialize_topics(&results)
+                Ok(termnote::deserialize_topics(&results))
             }
         }
-        Some(("list", _)) => {
-            terminal_ui.menu_loop(&mut terminal)?;
+        Some(("list", sub_matches)) => {
+            let tags = tag_filter(sub_matches);
+            let mut topics = processor.topics();
+            topics.retain(|topic| topic.has_tags(&tags));
+            sort_topics(&mut topics, sort_order(sub_matches)?);
+            let mut terminal_ui = TerminalUI::new(topics).with_profile(config.profile.clone());
+            let mut terminal: Terminal<CrosstermBackend<Stdout>> = ratatui::init();
+            let menu_rs = terminal_ui.menu_loop(&mut terminal);
+            restore_terminal();
+            menu_rs?;
+            // the menu can be left without a row selected, e.g. on an empty list
+            if terminal_ui.selected_id.is_empty() {
+                return Ok("".to_string());
+            }
             match terminal_ui.event {
-                MenuEvent::Execute => run_cmd(&terminal_ui.selected_cmd.to_owned()),
-                MenuEvent::Display => terminal_ui.selected_cmd,
-                _ => "".to_string(),
+                MenuEvent::Execute => {
+                    let topic = processor.find_topic(&terminal_ui.selected_id)?;
+                    if terminal_ui.selected_step.is_none() && !topic.steps.is_empty() {
+                        processor.record_topic_use(&topic.id)?;
+                        return run_workflow(
+                            &processor,
+                            &topic,
+                            &ExecContext::of(&topic),
+                            &HashMap::new(),
+                            false,
+                            terminal_ui.confirmed,
+                        );
+                    }
+                    let command = prepare_command(
+                        &topic,
+                        &terminal_ui.selected_cmd,
+                        &HashMap::new(),
+                        false,
+                        terminal_ui.confirmed,
+                    )?;
+                    processor.record_topic_use(&topic.id)?;
+                    command_result(processor.execute(
+                        &topic.id,
+                        &topic.title,
+                        &command,
+                        &ExecContext::of(&topic),
+                    )?)
+                }
+                MenuEvent::Display => {
+                    processor.record_topic_use(&terminal_ui.selected_id)?;
+                    Ok(terminal_ui.selected_cmd)
+                }
+                _ => Ok("".to_string()),
             }
         }
+        Some(("tags", _)) => {
+            let counts = processor.tag_counts();
+            if counts.is_empty() {
+                return Ok("No tags found".to_string());
+            }
+            let lines: Vec<String> = counts
+                .into_iter()
+                .map(|(tag, count)| format!("{} ({})", tag, count))
+                .collect();
+            Ok(lines.join("\n"))
+        }
+        Some(("fork", sub_matches)) => {
+            let key = required(sub_matches, "ID")?;
+            let origin = target_origin(sub_matches, &config, &mut processor)?;
+            let id = processor.fork(key, origin)?;
+            Ok(format!("Success: Forked topic as {}", id))
+        }
+        Some(("encrypt", _)) => {
+            if processor.is_encrypted() {
+                return Err(TermnoteError::Validation(
+                    "The library is already encrypted".to_string(),
+                ));
+            }
+            let cipher = Cipher::new(config.key_source().secret(true)?)?;
+            processor.set_cipher(Some(cipher))?;
+            Ok("Success: Encrypted the library".to_string())
+        }
+        Some(("decrypt", _)) => {
+            if !processor.is_encrypted() {
+                return Err(TermnoteError::Validation(
+                    "The library is not encrypted".to_string(),
+                ));
+            }
+            processor.set_cipher(None)?;
+            Ok("Success: Decrypted the library".to_string())
+        }
+        Some(("undo", _)) => {
+            let description = processor.undo()?;
+            Ok(format!("Success: Undid {}", description))
+        }
+        Some(("exec", sub_matches)) => {
+            let key = required(sub_matches, "ID")?;
+            let topic = processor.find_topic(key)?;
+            let values = placeholder_values(sub_matches, &template_source(&topic))?;
+            let context = ExecContext {
+                timeout: sub_matches
+                    .get_one::<u64>("timeout")
+                    .map(|secs| Duration::from_secs(*secs)),
+                capture: sub_matches.get_flag("capture"),
+                ..ExecContext::of(&topic)
+            };
+            if sub_matches.get_flag("dry-run") {
+                return dry_run(&topic, &context, &values);
+            }
+            if !topic.steps.is_empty() {
+                processor.record_topic_use(&topic.id)?;
+                return run_workflow(
+                    &processor,
+                    &topic,
+                    &context,
+                    &values,
+                    sub_matches.get_flag("yes"),
+                    false,
+                );
+            }
+            let command = prepare_command(
+                &topic,
+                &topic.command,
+                &values,
+                sub_matches.get_flag("yes"),
+                false,
+            )?;
+            processor.record_topic_use(&topic.id)?;
+            command_result(processor.execute(&topic.id, &topic.title, &command, &context)?)
+        }
+        Some(("history", sub_matches)) => run_history(sub_matches, &mut processor),
+        Some(("redo", _)) => {
+            let description = processor.redo()?;
+            Ok(format!("Success: Redid {}", description))
+        }
         Some(("url", sub_matches)) => match sub_matches.subcommand() {
-            Some(("list", _)) => {
-                terminal_url_ui.menu_loop(&mut terminal)?;
-                let selected_url: String = terminal_url_ui.selected_url;
-                if !selected_url.is_empty() {
-                    selected_url
-                } else {
-                    "".to_string()
+            Some(("list", list_matches)) => {
+                let tags = tag_filter(list_matches);
+                let mut links = processor.links();
+                links.retain(|link| link.has_tags(&tags));
+                sort_links(&mut links, sort_order(list_matches)?);
+                if links.is_empty() {
+                    return Ok("No links found".to_string());
                 }
+                let mut terminal_url_ui =
+                    TerminalUrlUI::new(links).with_profile(config.profile.clone());
+                let mut terminal: Terminal<CrosstermBackend<Stdout>> = ratatui::init();
+                let menu_rs = terminal_url_ui.menu_loop(&mut terminal);
+                restore_terminal();
+                menu_rs?;
+                if !terminal_url_ui.selected_id.is_empty() {
+                    processor.record_link_use(&terminal_url_ui.selected_id)?;
+                }
+                Ok(terminal_url_ui.selected_url)
             }
             Some(("add", url_matches)) => {
-                let title = url_matches
-                    .get_one::<String>("title")
-                    .expect("Title is required");
-                let url = url_matches
-                    .get_one::<String>("url")
-                    .expect("URL is required");
-                processor.add_url(title.to_string(), url.to_string())
+                let title = required(url_matches, "title")?;
+                let url = required(url_matches, "url")?;
+                let origin = target_origin(url_matches, &config, &mut processor)?;
+                let id = processor.add_url(
+                    title.to_string(),
+                    url.to_string(),
+                    tag_values(url_matches).unwrap_or_default(),
+                    origin,
+                )?;
+                Ok(format!("Success: Added link {}", id))
             }
             Some(("update", url_matches)) => {
-                let index_str: &String = url_matches
-                    .get_one::<String>("index")
-                    .expect("Index is required");
+                let key = required(url_matches, "id")?;
                 let title = url_matches.get_one::<String>("title");
                 let url = url_matches.get_one::<String>("url");
-                if let Ok(idx) = index_str.parse() {
-                    processor.update_url(idx, title, url)
-                } else {
-                    "Invalid index number".to_string()
-                }
+                processor.update_url(key, title, url, tag_values(url_matches))?;
+                Ok("Success: Updated link".to_string())
             }
             Some(("remove", url_matches)) => {
-                let index_str: Option<String> = url_matches.get_one::<String>("index").cloned();
-                if let Some(idx_str) = index_str {
-                    if let Ok(idx) = idx_str.parse() {
-                        processor.remove_url_by_index(idx)
-                    } else {
-                        "Invalid index number".to_string()
-                    }
-                } else {
-                    "Error: Must provide an index".to_string()
-                }
+                let key = required(url_matches, "id")?;
+                processor.remove_url(key)?;
+                Ok("Success: Removed link".to_string())
+            }
+            Some(("fork", url_matches)) => {
+                let key = required(url_matches, "ID")?;
+                let origin = target_origin(url_matches, &config, &mut processor)?;
+                let id = processor.fork_url(key, origin)?;
+                Ok(format!("Success: Forked link as {}", id))
             }
             Some(("search", url_matches)) => {
-                let query: &String = url_matches
-                    .get_one::<String>("query")
-                    .expect("Query is required");
-                let results = processor.search_links_by_title(query);
+                let query = search_query(url_matches)?;
+                let tags = tag_filter(url_matches);
+                let mut results = processor.search_links_by_title(&query)?;
+                results.retain(|link| link.has_tags(&tags));
+                sort_links(&mut results, sort_order(url_matches)?);
                 if results.is_empty() {
-                    "No links found matching the query".to_string()
+                    Ok("No links found matching the query".to_string())
                 } else {
-                    termnote::deserialize_links(&results)
+                    Ok(termnote::deserialize_links(&results))
                 }
             }
-            _ => "Missing subcommand for url".to_string(),
+            _ => Err(TermnoteError::Validation(
+                "Missing subcommand for url".to_string(),
+            )),
         },
-        _ => "Missing subcommand!".to_string(),
-    };
-    restore_terminal()?;
-    if !message.is_empty() {
-        display_text(&message);
+        Some(("sync", sub_matches)) => match sub_matches.subcommand() {
+            Some(("init", init_matches)) => {
+                let remote = init_matches.get_one::<String>("REMOTE");
+                processor.sync_init(remote.map(|remote| remote.as_str()))?;
+                Ok(format!("Success: Syncing {}", config.home.display()))
+            }
+            Some(("pull", _)) => processor.sync_pull(),
+            Some(("push", _)) => {
+                processor.sync_push()?;
+                Ok("Success: Pushed the library".to_string())
+            }
+            Some(("status", _)) => processor.sync_status(),
+            _ => Err(TermnoteError::Validation(
+                "Missing subcommand for sync".to_string(),
+            )),
+        },
+        Some(("backup", sub_matches)) => match sub_matches.subcommand() {
+            Some(("list", _)) => {
+                let backups = processor.list_backups()?;
+                if backups.is_empty() {
+                    Ok("No backups found".to_string())
+                } else {
+                    Ok(termnote::deserialize_backups(&backups))
+                }
+            }
+            Some(("restore", backup_matches)) => {
+                let id = required(backup_matches, "ID")?;
+                let diff = processor.backup_diff(id)?;
+                if diff.is_empty() {
+                    return Ok("Backup matches the current library, nothing to restore".to_string());
+                }
+                display_text("Restoring the backup changes the library as follows:");
+                print!("{}", termnote::deserialize_library_diff(&diff));
+                if !backup_matches.get_flag("yes") && !prompt_confirmation("Restore this backup?")?
+                {
+                    return Ok("Restore aborted".to_string());
+                }
+                processor.restore_backup(id)?;
+                Ok(format!("Success: Restored backup {}", id))
+            }
+            _ => Err(TermnoteError::Validation(
+                "Missing subcommand for backup".to_string(),
+            )),
+        },
+        _ => Err(TermnoteError::Validation("Missing subcommand!".to_string())),
+    }
+}
+
+fn main() {
+    let matches: ArgMatches = cmd().get_matches();
+    match run(&matches) {
+        Ok(message) => {
+            if !message.is_empty() {
+                display_text(&message);
+            }
+        }
+        Err(e) => {
+            // the command already reported its failure itself
+            if !matches!(e, TermnoteError::Command(_)) {
+                display_error(&e);
+            }
+            process::exit(e.exit_code());
+        }
     }
-    Ok(())
 }